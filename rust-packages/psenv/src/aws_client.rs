@@ -2,6 +2,7 @@ use anyhow::{Context, Result};
 use aws_config::meta::region::RegionProviderChain;
 use aws_config::profile::ProfileFileCredentialsProvider;
 use aws_config::{BehaviorVersion, Region};
+use aws_sdk_ssm::types::ParameterType;
 use aws_sdk_ssm::Client;
 use log::debug;
 
@@ -9,6 +10,13 @@ pub struct AwsClient {
     ssm_client: Client,
 }
 
+/// A parameter value together with its Parameter Store version
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub value: String,
+    pub version: i64,
+}
+
 impl AwsClient {
     pub async fn new(region: Option<&str>, profile: Option<&str>) -> Result<Self> {
         let mut config_loader = aws_config::defaults(BehaviorVersion::latest());
@@ -36,7 +44,7 @@ impl AwsClient {
         Ok(AwsClient { ssm_client })
     }
 
-    pub async fn get_parameter(&self, name: &str) -> Result<Option<String>> {
+    pub async fn get_parameter(&self, name: &str) -> Result<Option<Parameter>> {
         debug!("Getting parameter: {}", name);
 
         match self
@@ -50,8 +58,11 @@ impl AwsClient {
             Ok(result) => {
                 if let Some(parameter) = result.parameter {
                     if let Some(value) = parameter.value {
-                        debug!("Successfully retrieved parameter: {}", name);
-                        Ok(Some(value))
+                        debug!("Successfully retrieved parameter: {} (version {})", name, parameter.version);
+                        Ok(Some(Parameter {
+                            value,
+                            version: parameter.version,
+                        }))
                     } else {
                         debug!("Parameter {} has no value", name);
                         Ok(None)
@@ -76,4 +87,55 @@ impl AwsClient {
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Lists all parameters directly under a path, keyed by name without the path
+    pub async fn get_parameters_by_path(&self, path: &str) -> Result<Vec<(String, String)>> {
+        debug!("Listing parameters under: {}", path);
+
+        let mut parameters = Vec::new();
+        let mut pages = self
+            .ssm_client
+            .get_parameters_by_path()
+            .path(path)
+            .recursive(false)
+            .with_decryption(true)
+            .into_paginator()
+            .send();
+
+        while let Some(page) = pages.next().await {
+            let page = page
+                .map_err(|err| anyhow::anyhow!("AWS SSM error: {}", err.into_service_error()))
+                .with_context(|| format!("Failed to list parameters under: {}", path))?;
+
+            for parameter in page.parameters() {
+                if let (Some(name), Some(value)) = (parameter.name(), parameter.value()) {
+                    let key = name.strip_prefix(path).unwrap_or(name).trim_start_matches('/');
+                    parameters.push((key.to_string(), value.to_string()));
+                }
+            }
+        }
+
+        debug!("Found {} parameters under {}", parameters.len(), path);
+        Ok(parameters)
+    }
+
+    /// Creates a new parameter, as a SecureString when `secure` is set
+    pub async fn put_parameter(&self, name: &str, value: &str, secure: bool) -> Result<()> {
+        debug!("Putting parameter: {}", name);
+
+        let parameter_type = if secure { ParameterType::SecureString } else { ParameterType::String };
+        self.ssm_client
+            .put_parameter()
+            .name(name)
+            .value(value)
+            .r#type(parameter_type)
+            .overwrite(false)
+            .send()
+            .await
+            .map_err(|err| anyhow::anyhow!("AWS SSM error: {}", err.into_service_error()))
+            .with_context(|| format!("Failed to put parameter: {}", name))?;
+
+        debug!("Successfully stored parameter: {}", name);
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::{anyhow, bail, Result};
+
+/// Condition of a `# @if ...` template block
+///
+/// Supports `==`, `!=`, `&&`, `||`, `!` and parentheses. Operands are variable names or
+/// quoted strings; a bare variable is true when it is set to anything but an empty string,
+/// `false`, `0`, `no` or `off`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Var(String),
+    Str(String),
+    Eq(Box<Condition>, Box<Condition>),
+    Ne(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Eq,
+    Ne,
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+impl Condition {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let tokens = tokenize(expression)?;
+        if tokens.is_empty() {
+            bail!("empty condition");
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let condition = parser.or()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            bail!("unexpected {:?} in condition", token);
+        }
+        Ok(condition)
+    }
+
+    /// Evaluates the condition, looking up variables with `lookup`
+    pub fn evaluate(&self, lookup: &dyn Fn(&str) -> Option<String>) -> bool {
+        match self {
+            Condition::Var(_) | Condition::Str(_) => {
+                let value = self.value(lookup);
+                !matches!(value.to_lowercase().as_str(), "" | "false" | "0" | "no" | "off")
+            }
+            Condition::Eq(a, b) => a.value(lookup) == b.value(lookup),
+            Condition::Ne(a, b) => a.value(lookup) != b.value(lookup),
+            Condition::Not(inner) => !inner.evaluate(lookup),
+            Condition::And(a, b) => a.evaluate(lookup) && b.evaluate(lookup),
+            Condition::Or(a, b) => a.evaluate(lookup) || b.evaluate(lookup),
+        }
+    }
+
+    /// String value of an operand; unset variables are empty, comparisons are "true"/"false"
+    fn value(&self, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+        match self {
+            Condition::Var(name) => lookup(name).unwrap_or_default(),
+            Condition::Str(value) => value.clone(),
+            _ => self.evaluate(lookup).to_string(),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            _ if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '=' | '!' | '&' | '|' => match (c, chars.peek()) {
+                ('=', Some('=')) | ('!', Some('=')) | ('&', Some('&')) | ('|', Some('|')) => {
+                    chars.next();
+                    match c {
+                        '=' => Token::Eq,
+                        '!' => Token::Ne,
+                        '&' => Token::And,
+                        _ => Token::Or,
+                    }
+                }
+                ('!', _) => Token::Not,
+                _ => bail!("unexpected '{}' in condition", c),
+            },
+            '"' | '\'' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(other) => value.push(other),
+                        None => bail!("unterminated string in condition"),
+                    }
+                }
+                Token::Str(value)
+            }
+            _ if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut name = c.to_string();
+                while let Some(&next) = chars.peek().filter(|n| n.is_ascii_alphanumeric() || matches!(n, '_' | '.' | '-')) {
+                    name.push(next);
+                    chars.next();
+                }
+                Token::Ident(name)
+            }
+            _ => bail!("unexpected '{}' in condition", c),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, lowest precedence first
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next_if(&mut self, token: &Token) -> bool {
+        let matched = self.tokens.get(self.pos) == Some(token);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn or(&mut self) -> Result<Condition> {
+        let mut left = self.and()?;
+        while self.next_if(&Token::Or) {
+            left = Condition::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Condition> {
+        let mut left = self.unary()?;
+        while self.next_if(&Token::And) {
+            left = Condition::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Condition> {
+        if self.next_if(&Token::Not) {
+            return Ok(Condition::Not(Box::new(self.unary()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Condition> {
+        let left = self.primary()?;
+        if self.next_if(&Token::Eq) {
+            return Ok(Condition::Eq(Box::new(left), Box::new(self.primary()?)));
+        }
+        if self.next_if(&Token::Ne) {
+            return Ok(Condition::Ne(Box::new(left), Box::new(self.primary()?)));
+        }
+        Ok(left)
+    }
+
+    fn primary(&mut self) -> Result<Condition> {
+        let token = self.tokens.get(self.pos).cloned().ok_or_else(|| anyhow!("condition ends unexpectedly"))?;
+        self.pos += 1;
+        match token {
+            Token::Ident(name) => Ok(Condition::Var(name)),
+            Token::Str(value) => Ok(Condition::Str(value)),
+            Token::Open => {
+                let inner = self.or()?;
+                if !self.next_if(&Token::Close) {
+                    bail!("missing ')' in condition");
+                }
+                Ok(inner)
+            }
+            other => bail!("unexpected {:?} in condition", other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn eval(expression: &str, vars: &[(&str, &str)]) -> bool {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Condition::parse(expression).unwrap().evaluate(&|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_equality_and_truthiness() {
+        assert!(eval(r#"DEPLOY_ENV == "prod""#, &[("DEPLOY_ENV", "prod")]));
+        assert!(!eval(r#"DEPLOY_ENV == "prod""#, &[("DEPLOY_ENV", "dev")]));
+        assert!(eval("DEPLOY_ENV != 'prod'", &[]));
+        assert!(eval("ENABLE_SMTP", &[("ENABLE_SMTP", "true")]));
+        assert!(!eval("ENABLE_SMTP", &[("ENABLE_SMTP", "0")]));
+        assert!(!eval("ENABLE_SMTP", &[]));
+    }
+
+    #[test]
+    fn test_boolean_operators() {
+        let vars = [("DEPLOY_ENV", "prod"), ("REGION", "eu")];
+        assert!(eval(r#"DEPLOY_ENV == "prod" && REGION == "eu""#, &vars));
+        assert!(eval(r#"DEPLOY_ENV == "dev" || REGION == "eu""#, &vars));
+        assert!(!eval(r#"!(DEPLOY_ENV == "prod")"#, &vars));
+        // && binds tighter than ||
+        assert!(eval(r#"DEPLOY_ENV == "dev" && REGION == "us" || REGION == "eu""#, &vars));
+    }
+
+    #[test]
+    fn test_invalid_conditions() {
+        assert!(Condition::parse("").is_err());
+        assert!(Condition::parse("A = B").is_err());
+        assert!(Condition::parse("(A == B").is_err());
+        assert!(Condition::parse("A == 'b").is_err());
+        assert!(Condition::parse("A == B C").is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+use std::ops::Range;
+
+/// An error in a template, pointing at the offending source text
+///
+/// Displayed compiler-style, with the source line and a caret under the bad part:
+///
+/// ```text
+/// Required variable 'PG_HOTS' not found in context, shell environment, or default
+///   --> .env.example:12:27
+///    |
+/// 12 | DATABASE_URL=postgresql://${PG_HOTS}/app
+///    |                           ^^^^^^^^^^
+///    = help: did you mean PG_HOST?
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub file: Option<String>,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column of the first highlighted character
+    pub column: usize,
+    /// Number of highlighted characters, at least 1
+    pub width: usize,
+    pub source_line: String,
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    /// Highlights the byte range `span` of `source_line`
+    pub fn new(message: impl Into<String>, line: usize, source_line: &str, span: Range<usize>) -> Self {
+        let start = floor_char_boundary(source_line, span.start);
+        let end = floor_char_boundary(source_line, span.end.max(start));
+        Diagnostic {
+            message: message.into(),
+            file: None,
+            line,
+            column: source_line[..start].chars().count() + 1,
+            width: source_line[start..end].chars().count().max(1),
+            source_line: source_line.to_string(),
+            hint: None,
+        }
+    }
+
+    pub fn with_file(mut self, file: Option<String>) -> Self {
+        self.file = file;
+        self
+    }
+
+    pub fn with_hint(mut self, hint: Option<String>) -> Self {
+        self.hint = hint;
+        self
+    }
+
+    /// Where the error is, e.g. `.env.example:12:27`
+    pub fn location(&self) -> String {
+        match &self.file {
+            Some(file) => format!("{}:{}:{}", file, self.line, self.column),
+            None => format!("line {}, column {}", self.line, self.column),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let gutter = self.line.to_string().len();
+        writeln!(f, "{}", self.message)?;
+        writeln!(f, "{:gutter$}--> {}", "", self.location())?;
+        writeln!(f, "{:gutter$} |", "")?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        write!(f, "{:gutter$} | {}{}", "", " ".repeat(self.column - 1), "^".repeat(self.width))?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n{:gutter$} = help: {}", "", hint)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// The candidate closest to `name` by edit distance, if any is close enough to be a likely typo
+pub fn closest_match<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(&name.to_lowercase(), &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance, counting a swap of two adjacent characters as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    let mut index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let source = "DATABASE_URL=postgresql://${PG_HOTS}/app";
+        let diagnostic = Diagnostic::new("Required variable 'PG_HOTS' not found", 12, source, 26..36)
+            .with_file(Some(".env.example".to_string()))
+            .with_hint(Some("did you mean PG_HOST?".to_string()));
+
+        assert_eq!(diagnostic.column, 27);
+        assert_eq!(
+            diagnostic.to_string(),
+            "Required variable 'PG_HOTS' not found\n  \
+             --> .env.example:12:27\n   |\n\
+             12 | DATABASE_URL=postgresql://${PG_HOTS}/app\n   \
+             |                           ^^^^^^^^^^\n   \
+             = help: did you mean PG_HOST?"
+        );
+    }
+
+    #[test]
+    fn test_columns_count_characters() {
+        let diagnostic = Diagnostic::new("bad", 1, "NAME=\"Zoë ${X\"", 11..14);
+        assert_eq!(diagnostic.column, 11);
+        assert_eq!(diagnostic.width, 3);
+        assert_eq!(diagnostic.location(), "line 1, column 11");
+    }
+
+    #[test]
+    fn test_closest_match() {
+        let keys = ["PG_HOST", "PG_PORT", "PG_PASSWORD", "REDIS_URL"];
+
+        assert_eq!(closest_match("PG_HOTS", keys), Some("PG_HOST"));
+        assert_eq!(closest_match("pg_host", keys), Some("PG_HOST"));
+        assert_eq!(closest_match("PG_PASWORD", keys), Some("PG_PASSWORD"));
+        assert_eq!(closest_match("SMTP_USER", keys), None);
+        assert_eq!(closest_match("PG_HOST", ["PG_HOST"]), None);
+    }
+}
@@ -1,10 +1,13 @@
 use anyhow::{Context, Result};
 use clap::ValueEnum;
 use log::{debug, info};
+use regex::Regex;
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 
+use crate::template_parser::{parse_value, KeyGrammar};
 use crate::PsenvError;
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -17,11 +20,35 @@ pub enum Strategy {
     Error,
 }
 
-pub struct EnvHandler;
+/// Output path that writes to stdout instead of a file
+pub const STDOUT: &str = "-";
+
+/// What happened to the output file
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileAction {
+    Created,
+    Updated,
+    Unchanged,
+    /// Written to stdout with `-o -`
+    Stdout,
+}
+
+pub struct EnvHandler {
+    /// Same entry regex as the template parser, so update mode sees the same keys
+    env_key_regex: Regex,
+}
 
 impl EnvHandler {
     pub fn new() -> Self {
-        EnvHandler
+        EnvHandler {
+            env_key_regex: KeyGrammar::default().entry_regex(),
+        }
+    }
+
+    pub fn with_key_grammar(mut self, key_grammar: KeyGrammar) -> Self {
+        self.env_key_regex = key_grammar.entry_regex();
+        self
     }
 
     pub fn handle_env_file(
@@ -29,55 +56,64 @@ impl EnvHandler {
         output_path: &str,
         values: &HashMap<String, String>,
         strategy: Strategy,
-    ) -> Result<()> {
+    ) -> Result<FileAction> {
         debug!("Handling .env file: {} with strategy: {:?}", output_path, strategy);
 
+        // There is no existing file to update or protect on stdout
+        if output_path == STDOUT {
+            print!("{}", format_env(values));
+            info!("Wrote {} variables to stdout", values.len());
+            return Ok(FileAction::Stdout);
+        }
+
         let output_exists = Path::new(output_path).exists();
 
-        match strategy {
+        let action = match strategy {
             Strategy::Error if output_exists => {
                 return Err(PsenvError::FileExists(
                     format!("Output file already exists: {}", output_path)
                 ).into());
             }
             Strategy::Overwrite => {
-                self.write_env_file(output_path, values)?;
+                self.write_env_file(output_path, values)?
             }
             Strategy::Update if output_exists => {
-                self.update_env_file(output_path, values)?;
+                self.update_env_file(output_path, values)?
             }
             _ => {
                 // For update when file doesn't exist, just create it
-                self.write_env_file(output_path, values)?;
+                self.write_env_file(output_path, values)?
             }
-        }
+        };
 
         info!("Successfully processed .env file: {}", output_path);
-        Ok(())
+        Ok(action)
     }
 
-    fn write_env_file(&self, path: &str, values: &HashMap<String, String>) -> Result<()> {
+    fn write_env_file(&self, path: &str, values: &HashMap<String, String>) -> Result<FileAction> {
         debug!("Writing new .env file: {}", path);
 
-        let mut content = String::new();
-        let mut sorted_keys: Vec<&String> = values.keys().collect();
-        sorted_keys.sort();
-
-        for key in sorted_keys {
-            if let Some(value) = values.get(key) {
-                content.push_str(&format!("{}={}\n", key, value));
-            }
+        let content = format_env(values);
+        let existing = fs::read_to_string(path).ok();
+        if existing.as_deref() == Some(content.as_str()) {
+            info!("No changes to .env file ({} variables)", values.len());
+            return Ok(FileAction::Unchanged);
         }
 
         fs::write(path, content)
             .with_context(|| format!("Failed to write .env file: {}", path))?;
 
-        info!("Created new .env file with {} variables", values.len());
-        Ok(())
+        if existing.is_some() {
+            info!("Rewrote .env file with {} variables", values.len());
+            Ok(FileAction::Updated)
+        } else {
+            info!("Created new .env file with {} variables", values.len());
+            Ok(FileAction::Created)
+        }
     }
 
 
-    fn update_env_file(&self, path: &str, new_values: &HashMap<String, String>) -> Result<()> {
+    fn update_env_file(&self, path: &str, new_values: &HashMap<String, String>) -> Result<FileAction> {
         debug!("Updating existing .env file: {}", path);
 
         let existing_content = fs::read_to_string(path)
@@ -85,33 +121,46 @@ impl EnvHandler {
 
         let (updated_content, updated_count, added_count) = self.update_preserve_format(&existing_content, new_values)?;
 
+        if updated_content == existing_content {
+            info!("No changes to .env file");
+            return Ok(FileAction::Unchanged);
+        }
+
         fs::write(path, updated_content)
             .with_context(|| format!("Failed to write .env file: {}", path))?;
 
         info!("Updated .env file: updated {} variables, added {} variables", updated_count, added_count);
-        Ok(())
+        Ok(FileAction::Updated)
     }
 
 
 
     fn update_preserve_format(&self, content: &str, new_values: &HashMap<String, String>) -> Result<(String, usize, usize)> {
-        use regex::Regex;
-        let env_key_regex = Regex::new(r"^#?\s*([A-Z_][A-Z0-9_]*)\s*=.*$").unwrap();
-
         let mut existing_keys = HashSet::new();
         let mut result = String::new();
         let mut updated_count = 0;
 
         // Process existing content line by line, replacing values where needed
-        for line in content.lines() {
-            if let Some(captures) = env_key_regex.captures(line) {
-                if let Some(key_match) = captures.get(1) {
+        let mut lines = content.lines();
+        while let Some(line) = lines.next() {
+            // Commented-out lines are kept as they are, never activated
+            let trimmed = line.trim_start();
+            if let Some(captures) = self.env_key_regex.captures(trimmed).filter(|c| c.get(1).is_none()) {
+                if let Some(key_match) = captures.get(2) {
                     let key = key_match.as_str();
                     existing_keys.insert(key.to_string());
 
                     if let Some(new_value) = new_values.get(key) {
-                        // Replace with new value
-                        result.push_str(&format!("{}={}\n", key, new_value));
+                        // A quoted value may go on over the next lines, which are replaced along with it
+                        let rest = captures.get(3).map_or("", |m| m.as_str());
+                        let mut continuation = lines.clone();
+                        if parse_value(rest, || continuation.next()).is_some() {
+                            lines = continuation;
+                        }
+                        // Replace with new value, keeping the indentation and any `export `
+                        let prefix_len = line.len() - trimmed.len() + key_match.start();
+                        result.push_str(&line[..prefix_len]);
+                        result.push_str(&format_line(key, new_value));
                         updated_count += 1;
                         debug!("Updated existing variable: {}", key);
                         continue;
@@ -131,7 +180,7 @@ impl EnvHandler {
         for key in new_keys {
             if !existing_keys.contains(key) {
                 if let Some(value) = new_values.get(key) {
-                    result.push_str(&format!("{}={}\n", key, value));
+                    result.push_str(&format_line(key, value));
                     added_count += 1;
                     debug!("Added new variable: {}", key);
                 }
@@ -142,6 +191,50 @@ impl EnvHandler {
     }
 }
 
+/// `KEY=value` lines sorted by key
+fn format_env(values: &HashMap<String, String>) -> String {
+    let mut sorted_keys: Vec<&String> = values.keys().collect();
+    sorted_keys.sort();
+    sorted_keys.iter().map(|key| format_line(key, &values[*key])).collect()
+}
+
+/// A `KEY=value` line, quoting values that wouldn't read back the same unquoted.
+///
+/// Quoting follows what Node's `dotenv` accepts: single and backtick quotes are
+/// literal, and double quotes only expand `\n` and `\r`, so a quote style is
+/// picked that needs no escapes at all when one exists. The template parser
+/// accepts more (see `parse_value`), but only forms both read the same way are written.
+fn format_line(key: &str, value: &str) -> String {
+    // dotenv starts a comment at any `#`, not just one after whitespace
+    let needs_quotes = value.contains(['\n', '\r', '"', '\'', '`', '#']) || value.trim() != value;
+    if !needs_quotes {
+        return format!("{}={}\n", key, value);
+    }
+
+    // dotenv folds CRLF into LF outside escapes, so `\r` must stay an escape
+    if !value.contains(['\'', '\n', '\r']) {
+        return format!("{}='{}'\n", key, value);
+    }
+    if !value.contains(['"', '\\']) {
+        return format!("{}=\"{}\"\n", key, value.replace('\r', "\\r"));
+    }
+    if !value.contains(['`', '\r']) {
+        return format!("{}=`{}`\n", key, value);
+    }
+
+    // Nothing dotenv reads verbatim is left; escape what psenv's own parser needs
+    let mut quoted = String::with_capacity(value.len() + 2);
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\r' => quoted.push_str("\\r"),
+            _ => quoted.push(c),
+        }
+    }
+    format!("{}=\"{}\"\n", key, quoted)
+}
+
 impl Default for EnvHandler {
     fn default() -> Self {
         Self::new()
@@ -170,6 +263,30 @@ mod tests {
         assert!(content.contains("KEY2=value2"));
     }
 
+    #[test]
+    fn test_handle_env_file_actions() {
+        let handler = EnvHandler::new();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        let path = path.to_str().unwrap();
+
+        let mut values = HashMap::new();
+        values.insert("KEY1".to_string(), "value1".to_string());
+
+        let action = handler.handle_env_file(path, &values, Strategy::Overwrite).unwrap();
+        assert_eq!(action, FileAction::Created);
+
+        let action = handler.handle_env_file(path, &values, Strategy::Overwrite).unwrap();
+        assert_eq!(action, FileAction::Unchanged);
+
+        let action = handler.handle_env_file(path, &values, Strategy::Update).unwrap();
+        assert_eq!(action, FileAction::Unchanged);
+
+        values.insert("KEY1".to_string(), "changed".to_string());
+        let action = handler.handle_env_file(path, &values, Strategy::Update).unwrap();
+        assert_eq!(action, FileAction::Updated);
+    }
+
 
     #[test]
     fn test_update_env_file() {
@@ -199,4 +316,124 @@ mod tests {
         // Should add new key at the end
         assert!(content.contains("NEW_KEY=new_value"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_update_recognizes_parser_keys() {
+        let handler = EnvHandler::new().with_key_grammar(KeyGrammar::Dotted);
+        let content = "node_env=development\nexport Umami_Secret=old\n  spring.datasource.url=jdbc:old\n";
+
+        let mut new_values = HashMap::new();
+        new_values.insert("node_env".to_string(), "production".to_string());
+        new_values.insert("Umami_Secret".to_string(), "new".to_string());
+        new_values.insert("spring.datasource.url".to_string(), "jdbc:new".to_string());
+
+        let (updated, updated_count, added_count) = handler.update_preserve_format(content, &new_values).unwrap();
+        assert_eq!(updated, "node_env=production\nexport Umami_Secret=new\n  spring.datasource.url=jdbc:new\n");
+        assert_eq!((updated_count, added_count), (3, 0));
+    }
+
+    /// Parses like Node's `dotenv` (lib/main.js `parse`), which output files are loaded with
+    fn dotenv_parse(src: &str) -> HashMap<String, String> {
+        let line = Regex::new(
+            r#"(?m)^\s*(?:export\s+)?([\w.-]+)(?:\s*=\s*?|:\s+?)(\s*'(?:\\'|[^'])*'|\s*"(?:\\"|[^"])*"|\s*`(?:\\`|[^`])*`|[^#\r\n]+)?\s*(?:#.*)?$"#,
+        )
+        .unwrap();
+        let src = src.replace("\r\n", "\n").replace('\r', "\n");
+
+        let mut values = HashMap::new();
+        for captures in line.captures_iter(&src) {
+            let mut value = captures.get(2).map_or("", |m| m.as_str()).trim().to_string();
+            let quote = value.chars().next();
+            if value.len() >= 2 && matches!(quote, Some('\'' | '"' | '`')) && value.ends_with(quote.unwrap()) {
+                value = value[1..value.len() - 1].to_string();
+            }
+            if quote == Some('"') {
+                value = value.replace("\\n", "\n").replace("\\r", "\r");
+            }
+            values.insert(captures[1].to_string(), value);
+        }
+        values
+    }
+
+    #[test]
+    fn test_values_are_quoted_when_needed() {
+        let mut values = HashMap::new();
+        values.insert("PLAIN".to_string(), "postgres://app@db/app#main".to_string());
+        values.insert("GREETING".to_string(), "hello # world".to_string());
+        values.insert("PRIVATE_KEY".to_string(), "-----BEGIN KEY-----\nabc\n-----END KEY-----".to_string());
+        values.insert("QUOTED".to_string(), "say \"hi\" C:\\tmp".to_string());
+        values.insert("PADDED".to_string(), " x ".to_string());
+        values.insert("APOSTROPHE".to_string(), "it's C:\\tmp".to_string());
+        values.insert("CARRIAGE".to_string(), "a\r\nb".to_string());
+
+        let content = format_env(&values);
+        assert_eq!(
+            content,
+            "APOSTROPHE=`it's C:\\tmp`\n\
+             CARRIAGE=\"a\\r\nb\"\n\
+             GREETING='hello # world'\n\
+             PADDED=' x '\n\
+             PLAIN='postgres://app@db/app#main'\n\
+             PRIVATE_KEY=\"-----BEGIN KEY-----\nabc\n-----END KEY-----\"\n\
+             QUOTED='say \"hi\" C:\\tmp'\n"
+        );
+
+        // Node's dotenv reads back the same values
+        assert_eq!(dotenv_parse(&content), values);
+
+        // So does psenv, for update mode and templates
+        let entries = crate::template_parser::TemplateParser::new().parse_str(&content).unwrap();
+        for entry in entries {
+            assert_eq!(entry.raw_value, values[&entry.key], "{}", entry.key);
+        }
+    }
+
+    #[test]
+    fn test_written_values_read_back_the_same() {
+        // Each of these reads differently under psenv's and dotenv's rules when written bare
+        // or double-quoted, so the writer has to pick a form both agree on
+        let mut values = HashMap::new();
+        values.insert("COLOR".to_string(), "#fff".to_string());
+        values.insert("ANCHOR".to_string(), "https://example.com/docs#intro".to_string());
+        values.insert("TAB".to_string(), "a\tb".to_string());
+        values.insert("ESCAPED_TAB".to_string(), "C:\\tmp\\new".to_string());
+        values.insert("BACKSLASHES".to_string(), "\\\\server\\share\\".to_string());
+        values.insert("MIXED".to_string(), "it's \"#1\"\tC:\\tmp\nnext".to_string());
+
+        let content = format_env(&values);
+        assert_eq!(dotenv_parse(&content), values);
+
+        let entries = crate::template_parser::TemplateParser::new().parse_str(&content).unwrap();
+        assert_eq!(entries.len(), values.len());
+        for entry in entries {
+            assert_eq!(entry.raw_value, values[&entry.key], "{}", entry.key);
+        }
+    }
+
+    #[test]
+    fn test_update_replaces_multi_line_values() {
+        let handler = EnvHandler::new();
+        let content = "PRIVATE_KEY=\"-----BEGIN KEY-----\nold\n-----END KEY-----\"\nNEXT=1\n";
+
+        let mut new_values = HashMap::new();
+        new_values.insert("PRIVATE_KEY".to_string(), "-----BEGIN KEY-----\nnew\n-----END KEY-----".to_string());
+
+        let (updated, updated_count, added_count) = handler.update_preserve_format(content, &new_values).unwrap();
+        assert_eq!(updated, "PRIVATE_KEY=\"-----BEGIN KEY-----\nnew\n-----END KEY-----\"\nNEXT=1\n");
+        assert_eq!((updated_count, added_count), (1, 0));
+    }
+
+    #[test]
+    fn test_update_keeps_commented_lines() {
+        let handler = EnvHandler::new();
+        let content = "DB_HOST=old\n# DB_PORT=5432\n";
+
+        let mut new_values = HashMap::new();
+        new_values.insert("DB_HOST".to_string(), "new".to_string());
+        new_values.insert("DB_PORT".to_string(), "6543".to_string());
+
+        let (updated, updated_count, added_count) = handler.update_preserve_format(content, &new_values).unwrap();
+        assert_eq!(updated, "DB_HOST=new\n# DB_PORT=5432\nDB_PORT=6543\n");
+        assert_eq!((updated_count, added_count), (1, 1));
+    }
+}
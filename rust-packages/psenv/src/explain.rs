@@ -0,0 +1,239 @@
+use std::collections::HashSet;
+
+use crate::resolver::{Resolution, Source};
+use crate::secret_masker::SecretMasker;
+use crate::template_parser::EnvEntry;
+use crate::template_renderer::{RefSource, TemplateRenderer};
+
+/// Renders the provenance and dependency tree of a single key
+pub struct Explainer<'a> {
+    resolution: &'a Resolution,
+    entries: &'a [EnvEntry],
+    prefix: &'a str,
+    masker: SecretMasker,
+    renderer: TemplateRenderer,
+    show_secrets: bool,
+}
+
+impl<'a> Explainer<'a> {
+    pub fn new(resolution: &'a Resolution, entries: &'a [EnvEntry], prefix: &'a str, show_secrets: bool) -> Self {
+        Explainer {
+            resolution,
+            entries,
+            prefix,
+            masker: SecretMasker::new().with_entries(entries),
+            renderer: TemplateRenderer::new(),
+            show_secrets,
+        }
+    }
+
+    pub fn explain(&self, key: &str) -> String {
+        let mut out = String::new();
+        let entry = self.entries.iter().find(|e| e.key == key);
+
+        match (self.resolution.context.get(key), entry) {
+            (Some(value), _) => {
+                out.push_str(&format!("{}={}\n", key, self.display_value(key, value)));
+                out.push_str(&format!("  source: {}\n", self.describe_source(key)));
+            }
+            (None, Some(entry)) => {
+                out.push_str(&format!("{} (unresolved)\n", key));
+                if let Some((_, err)) = self.resolution.render_errors.iter().find(|(k, _)| k == key) {
+                    out.push_str(&format!("  error: {} (at {})\n", err.message, err.location()));
+                    if let Some(hint) = &err.hint {
+                        out.push_str(&format!("  hint: {}\n", hint));
+                    }
+                } else {
+                    out.push_str(&format!(
+                        "  tried: ssm {}, shell env {}, template line {} (no literal value)\n",
+                        entry.parameter_path(self.prefix), key, entry.line
+                    ));
+                }
+            }
+            (None, None) => {
+                out.push_str(&format!("{} is not defined in the template\n", key));
+                return out;
+            }
+        }
+
+        if let Some(entry) = entry.filter(|e| e.file.is_some()) {
+            out.push_str(&format!("  defined in: {}\n", entry.location()));
+        }
+
+        if let Some(description) = entry.and_then(|e| e.description.as_deref()) {
+            out.push_str(&format!("  description: {}\n", description));
+        }
+
+        if let Some(Source::Computed { template, .. }) = self.resolution.sources.get(key) {
+            out.push_str(&format!("  expression: {}\n", template));
+        } else if let Some(entry) = entry.filter(|e| e.template().is_some_and(|t| self.renderer.contains_variables(t))) {
+            out.push_str(&format!("  expression: {}\n", entry.raw_value));
+        }
+
+        let mut visited = HashSet::new();
+        visited.insert(key.to_string());
+        let children = self.children(key);
+        if !children.is_empty() {
+            out.push_str("  dependencies:\n");
+            self.write_children(&children, "  ", &mut visited, &mut out);
+        }
+
+        out
+    }
+
+    /// Dependencies of a key as (name, description) pairs
+    fn children(&self, key: &str) -> Vec<(String, String)> {
+        match self.resolution.sources.get(key) {
+            Some(Source::Computed { refs, .. }) => refs
+                .iter()
+                .map(|r| {
+                    let display = self.display_value(&r.var.name, &r.value);
+                    let description = match r.source {
+                        RefSource::Context => self.describe_source(&r.var.name),
+                        RefSource::Environment => format!("shell env {}", r.var.name),
+                        RefSource::Default => format!("default taken from {}", r.var.expression()),
+                        RefSource::Alternative => format!("alternative taken from {}", r.var.expression()),
+                    };
+                    (r.var.name.clone(), format!("{}={}  [{}]", r.var.name, display, description))
+                })
+                .collect(),
+            Some(_) => Vec::new(),
+            None => {
+                // Unrendered computed entry: show which references are available
+                let Some(entry) = self.entries.iter().find(|e| e.key == key) else {
+                    return Vec::new();
+                };
+                self.renderer
+                    .references(entry.template().unwrap_or_default())
+                    .into_iter()
+                    .map(|var| match self.resolution.context.get(&var.name) {
+                        Some(value) => {
+                            let line = format!(
+                                "{}={}  [{}]",
+                                var.name,
+                                self.display_value(&var.name, value),
+                                self.describe_source(&var.name)
+                            );
+                            (var.name, line)
+                        }
+                        None => {
+                            let line = format!("{}  [unresolved]", var.name);
+                            (var.name, line)
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    fn write_children(
+        &self,
+        children: &[(String, String)],
+        indent: &str,
+        visited: &mut HashSet<String>,
+        out: &mut String,
+    ) {
+        for (idx, (name, line)) in children.iter().enumerate() {
+            let last = idx + 1 == children.len();
+            out.push_str(&format!("{}{}{}\n", indent, if last { "└── " } else { "├── " }, line));
+
+            if visited.insert(name.clone()) {
+                let nested = self.children(name);
+                let nested_indent = format!("{}{}", indent, if last { "    " } else { "│   " });
+                self.write_children(&nested, &nested_indent, visited, out);
+                visited.remove(name);
+            }
+        }
+    }
+
+    fn describe_source(&self, key: &str) -> String {
+        match self.resolution.sources.get(key) {
+            Some(source) => source.describe(key),
+            None => "unknown".to_string(),
+        }
+    }
+
+    fn display_value(&self, key: &str, value: &str) -> String {
+        if self.show_secrets || !self.resolution.is_sensitive(key, &self.masker) {
+            value.to_string()
+        } else {
+            self.masker.mask_value(value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::Resolver;
+
+    fn entry(key: &str, raw_value: &str, line: usize) -> EnvEntry {
+        EnvEntry::new(key, raw_value, line)
+    }
+
+    fn resolve(entries: &[EnvEntry]) -> Resolution {
+        let mut resolution = Resolution::default();
+        resolution.context.insert("PG_USER".to_string(), "app".to_string());
+        resolution.sources.insert("PG_USER".to_string(), Source::Literal { line: 1 });
+        resolution.context.insert("PG_PASSWORD".to_string(), "hunter2".to_string());
+        resolution.sources.insert(
+            "PG_PASSWORD".to_string(),
+            Source::Ssm { path: "/studio-dev/PG_PASSWORD".to_string(), version: 3 },
+        );
+        Resolver::new().render_computed(entries, &mut resolution);
+        resolution
+    }
+
+    #[test]
+    fn test_explain_dependency_tree() {
+        let mut entries = vec![
+            entry("PG_USER", "app", 1),
+            entry("PG_PASSWORD", "", 2),
+            entry("PG_HOST", "${PSENV_TEST_UNSET_CTX_HOST:-localhost}", 3),
+            entry("DATABASE_URL", "postgresql://${PG_USER}:${PG_PASSWORD}@${PG_HOST}", 4),
+        ];
+        entries[3].description = Some("Connection string for the app".to_string());
+        let resolution = resolve(&entries);
+        let output = Explainer::new(&resolution, &entries, "/studio-dev/", false).explain("DATABASE_URL");
+
+        assert!(output.contains("source: computed, template line 4"));
+        assert!(output.contains("description: Connection string for the app"));
+        assert!(output.contains("├── PG_USER=app  [template literal, line 1]"));
+        assert!(output.contains("[ssm /studio-dev/PG_PASSWORD (version 3)]"));
+        assert!(output.contains("└── PG_HOST=localhost  [computed, template line 3]"));
+        assert!(output.contains("    └── PSENV_TEST_UNSET_CTX_HOST=localhost  [default taken from ${PSENV_TEST_UNSET_CTX_HOST:-localhost}]"));
+
+        // The URL embeds the password, so it is masked along with the password itself
+        assert!(!output.contains("hunter2"));
+    }
+
+    #[test]
+    fn test_explain_show_secrets() {
+        let entries = vec![entry("PG_PASSWORD", "", 2)];
+        let resolution = resolve(&entries);
+        let output = Explainer::new(&resolution, &entries, "/studio-dev/", true).explain("PG_PASSWORD");
+
+        assert!(output.contains("PG_PASSWORD=hunter2"));
+    }
+
+    #[test]
+    fn test_explain_unresolved() {
+        let entries = vec![
+            entry("API_TOKEN", "", 1),
+            entry("CALLBACK_URL", "${PSENV_TEST_UNSET_HOST}/callback", 2),
+        ];
+        let resolution = resolve(&entries);
+        let explainer = Explainer::new(&resolution, &entries, "/studio-dev/", false);
+
+        let output = explainer.explain("API_TOKEN");
+        assert!(output.contains("API_TOKEN (unresolved)"));
+        assert!(output.contains("ssm /studio-dev/API_TOKEN"));
+
+        let output = explainer.explain("CALLBACK_URL");
+        assert!(output.contains("error: Required variable 'PSENV_TEST_UNSET_HOST'"));
+        assert!(output.contains("└── PSENV_TEST_UNSET_HOST  [unresolved]"));
+
+        let output = explainer.explain("NOT_THERE");
+        assert!(output.contains("not defined in the template"));
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::{anyhow, Result};
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+/// A `|name` transformation of the result of a `${...}` expression
+///
+/// Filters are chained left to right, each taking the output of the previous one:
+/// `${API_SECRET|trim|sha256}`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    /// Percent-encodes everything but the RFC 3986 unreserved characters
+    UrlEncode,
+    Base64,
+    /// Decodes standard base64, with or without padding
+    Base64Decode,
+    Lower,
+    Upper,
+    Trim,
+    /// Hex-encoded SHA-256 digest
+    Sha256,
+    /// A quoted JSON string
+    Json,
+}
+
+impl Filter {
+    pub const NAMES: [&'static str; 8] = ["urlencode", "base64", "base64decode", "lower", "upper", "trim", "sha256", "json"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        let filter = match name {
+            "urlencode" => Filter::UrlEncode,
+            "base64" => Filter::Base64,
+            "base64decode" => Filter::Base64Decode,
+            "lower" => Filter::Lower,
+            "upper" => Filter::Upper,
+            "trim" => Filter::Trim,
+            "sha256" => Filter::Sha256,
+            "json" => Filter::Json,
+            _ => return None,
+        };
+        Some(filter)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::UrlEncode => "urlencode",
+            Filter::Base64 => "base64",
+            Filter::Base64Decode => "base64decode",
+            Filter::Lower => "lower",
+            Filter::Upper => "upper",
+            Filter::Trim => "trim",
+            Filter::Sha256 => "sha256",
+            Filter::Json => "json",
+        }
+    }
+
+    pub fn apply(&self, value: &str) -> Result<String> {
+        let filtered = match self {
+            Filter::UrlEncode => url_encode(value),
+            Filter::Base64 => base64::engine::general_purpose::STANDARD.encode(value),
+            Filter::Base64Decode => {
+                let engine = GeneralPurpose::new(
+                    &alphabet::STANDARD,
+                    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+                );
+                let bytes = engine.decode(value).map_err(|e| anyhow!("not valid base64 ({})", e))?;
+                String::from_utf8(bytes).map_err(|_| anyhow!("the decoded value is not valid UTF-8"))?
+            }
+            Filter::Lower => value.to_lowercase(),
+            Filter::Upper => value.to_uppercase(),
+            Filter::Trim => value.trim().to_string(),
+            Filter::Sha256 => format!("{:x}", Sha256::digest(value.as_bytes())),
+            Filter::Json => serde_json::to_string(value)?,
+        };
+        Ok(filtered)
+    }
+}
+
+fn url_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(name: &str, value: &str) -> String {
+        Filter::parse(name).unwrap().apply(value).unwrap()
+    }
+
+    #[test]
+    fn test_filters() {
+        assert_eq!(apply("urlencode", "p@ss/w:rd ü~"), "p%40ss%2Fw%3Ard%20%C3%BC~");
+        assert_eq!(apply("base64", "app:secret"), "YXBwOnNlY3JldA==");
+        assert_eq!(apply("base64decode", "YXBwOnNlY3JldA=="), "app:secret");
+        assert_eq!(apply("base64decode", "YXBwOnNlY3JldA"), "app:secret");
+        assert_eq!(apply("lower", "Prod"), "prod");
+        assert_eq!(apply("upper", "prod"), "PROD");
+        assert_eq!(apply("trim", "  token\n"), "token");
+        assert_eq!(apply("sha256", "abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(apply("json", "say \"hi\"\n"), r#""say \"hi\"\n""#);
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for name in Filter::NAMES {
+            assert_eq!(Filter::parse(name).unwrap().name(), name);
+        }
+        assert_eq!(Filter::parse("md5"), None);
+    }
+
+    #[test]
+    fn test_base64decode_errors() {
+        let err = Filter::Base64Decode.apply("not base64!").unwrap_err();
+        assert!(err.to_string().starts_with("not valid base64"));
+
+        let err = Filter::Base64Decode.apply("/w==").unwrap_err();
+        assert_eq!(err.to_string(), "the decoded value is not valid UTF-8");
+    }
+}
@@ -0,0 +1,240 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashSet};
+
+use crate::template_parser::EnvEntry;
+use crate::template_renderer::TemplateRenderer;
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum GraphFormat {
+    #[value(name = "dot")]
+    Dot,
+    #[value(name = "mermaid")]
+    Mermaid,
+    #[value(name = "json")]
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    /// Template entry with a literal (or empty) value, resolved from Parameter Store
+    Raw,
+    /// Template entry containing `${...}` references
+    Computed,
+    /// Referenced variable that is not defined in the template
+    Unresolved,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Node {
+    pub key: String,
+    pub kind: NodeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+/// Dependency edge: `to` is computed from `from`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DependencyGraph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+impl DependencyGraph {
+    /// Builds the raw/computed dependency graph from the references in each entry
+    pub fn build(entries: &[EnvEntry]) -> Self {
+        let renderer = TemplateRenderer::new();
+        let defined: HashSet<&str> = entries.iter().map(|e| e.key.as_str()).collect();
+        let mut graph = DependencyGraph::default();
+        let mut unresolved = BTreeSet::new();
+
+        for entry in entries {
+            let template = entry.template().unwrap_or_default();
+            let refs = renderer.references(template);
+            // Same split as the resolver: escape-only values are raw, malformed ones computed
+            let kind = if renderer.contains_variables(template) { NodeKind::Computed } else { NodeKind::Raw };
+            graph.nodes.push(Node {
+                key: entry.key.clone(),
+                kind,
+                line: Some(entry.line),
+            });
+
+            for var in refs {
+                if !defined.contains(var.name.as_str()) {
+                    unresolved.insert(var.name.clone());
+                }
+                let edge = Edge {
+                    default: var.default().map(String::from),
+                    from: var.name,
+                    to: entry.key.clone(),
+                };
+                if !graph.edges.contains(&edge) {
+                    graph.edges.push(edge);
+                }
+            }
+        }
+
+        graph.nodes.extend(unresolved.into_iter().map(|key| Node {
+            key,
+            kind: NodeKind::Unresolved,
+            line: None,
+        }));
+
+        graph
+    }
+
+    pub fn render(&self, format: &GraphFormat) -> Result<String> {
+        match format {
+            GraphFormat::Dot => Ok(self.to_dot()),
+            GraphFormat::Mermaid => Ok(self.to_mermaid()),
+            GraphFormat::Json => Ok(serde_json::to_string_pretty(self)? + "\n"),
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph psenv {\n  rankdir=LR;\n");
+
+        for node in &self.nodes {
+            let attrs = match node.kind {
+                NodeKind::Raw => "shape=box",
+                NodeKind::Computed => "shape=ellipse",
+                NodeKind::Unresolved => "shape=box, style=dashed",
+            };
+            out.push_str(&format!("  \"{}\" [{}];\n", node.key, attrs));
+        }
+
+        for edge in &self.edges {
+            match &edge.default {
+                Some(default) => out.push_str(&format!(
+                    "  \"{}\" -> \"{}\" [label=\"default: {}\"];\n",
+                    edge.from,
+                    edge.to,
+                    default.replace('\\', "\\\\").replace('"', "\\\"")
+                )),
+                None => out.push_str(&format!("  \"{}\" -> \"{}\";\n", edge.from, edge.to)),
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("graph LR\n");
+
+        // Mermaid node ids are positional so that any key name is a valid id
+        let id = |key: &str| self.nodes.iter().position(|n| n.key == key).unwrap_or_default();
+
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let shape = match node.kind {
+                NodeKind::Raw => format!("n{}[\"{}\"]", idx, node.key),
+                NodeKind::Computed => format!("n{}([\"{}\"])", idx, node.key),
+                NodeKind::Unresolved => format!("n{}{{{{\"{}\"}}}}", idx, node.key),
+            };
+            out.push_str(&format!("  {}\n", shape));
+        }
+
+        for edge in &self.edges {
+            match &edge.default {
+                Some(default) => out.push_str(&format!(
+                    "  n{} -.->|\"default: {}\"| n{}\n",
+                    id(&edge.from),
+                    default.replace('"', "#quot;"),
+                    id(&edge.to)
+                )),
+                None => out.push_str(&format!("  n{} --> n{}\n", id(&edge.from), id(&edge.to))),
+            }
+        }
+
+        let unresolved: Vec<String> = self.nodes.iter().enumerate()
+            .filter(|(_, n)| n.kind == NodeKind::Unresolved)
+            .map(|(idx, _)| format!("n{}", idx))
+            .collect();
+        if !unresolved.is_empty() {
+            out.push_str("  classDef unresolved stroke-dasharray: 5 5\n");
+            out.push_str(&format!("  class {} unresolved\n", unresolved.join(",")));
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<EnvEntry> {
+        vec![
+            EnvEntry::new("APP_HOST", "${CTX_HOST:-localhost}", 1),
+            EnvEntry::new("APP_PORT", "3000", 2),
+            EnvEntry::new("APP_URL", "http://${APP_HOST}:${APP_PORT}", 3),
+            EnvEntry::new("CALLBACK_URL", "${APP_URL}/cb", 4),
+        ]
+    }
+
+    #[test]
+    fn test_build_graph() {
+        let graph = DependencyGraph::build(&entries());
+
+        let kinds: Vec<(&str, NodeKind)> = graph.nodes.iter().map(|n| (n.key.as_str(), n.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("APP_HOST", NodeKind::Computed),
+                ("APP_PORT", NodeKind::Raw),
+                ("APP_URL", NodeKind::Computed),
+                ("CALLBACK_URL", NodeKind::Computed),
+                ("CTX_HOST", NodeKind::Unresolved),
+            ]
+        );
+
+        assert_eq!(graph.edges.len(), 4);
+        assert_eq!(graph.edges[0].from, "CTX_HOST");
+        assert_eq!(graph.edges[0].default.as_deref(), Some("localhost"));
+        assert!(graph.edges.iter().any(|e| e.from == "APP_URL" && e.to == "CALLBACK_URL"));
+    }
+
+    #[test]
+    fn test_dot_output() {
+        let dot = DependencyGraph::build(&entries()).to_dot();
+
+        assert!(dot.starts_with("digraph psenv {"));
+        assert!(dot.contains("\"APP_PORT\" [shape=box];"));
+        assert!(dot.contains("\"CTX_HOST\" [shape=box, style=dashed];"));
+        assert!(dot.contains("\"CTX_HOST\" -> \"APP_HOST\" [label=\"default: localhost\"];"));
+        assert!(dot.contains("\"APP_URL\" -> \"CALLBACK_URL\";"));
+    }
+
+    #[test]
+    fn test_mermaid_output() {
+        let mermaid = DependencyGraph::build(&entries()).to_mermaid();
+
+        assert!(mermaid.starts_with("graph LR\n"));
+        assert!(mermaid.contains("n1[\"APP_PORT\"]"));
+        assert!(mermaid.contains("n2([\"APP_URL\"])"));
+        assert!(mermaid.contains("n4{{\"CTX_HOST\"}}"));
+        assert!(mermaid.contains("n4 -.->|\"default: localhost\"| n0"));
+        assert!(mermaid.contains("n2 --> n3"));
+        assert!(mermaid.contains("class n4 unresolved"));
+    }
+
+    #[test]
+    fn test_json_output() {
+        let json = DependencyGraph::build(&entries()).render(&GraphFormat::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(value["nodes"][4]["kind"], "unresolved");
+        assert!(value["nodes"][4].get("line").is_none());
+        assert_eq!(value["edges"][0]["default"], "localhost");
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::HashSet;
+
+use crate::template_parser::EnvEntry;
+use crate::template_renderer::TemplateRenderer;
+
+/// Prefix that marks a filter pattern as a regular expression instead of a glob
+const REGEX_PREFIX: &str = "re:";
+
+/// Selects template keys with `--only` and `--exclude` patterns
+///
+/// Patterns are globs (`COMMON_*`, `*_READONLY_*`, `DB_?`) unless prefixed with `re:`,
+/// in which case the rest is a regular expression matched anywhere in the key.
+pub struct KeyFilter {
+    only: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl KeyFilter {
+    pub fn new(only: &[String], exclude: &[String]) -> Result<Self> {
+        Ok(KeyFilter {
+            only: only.iter().map(|p| compile_pattern(p)).collect::<Result<_>>()?,
+            exclude: exclude.iter().map(|p| compile_pattern(p)).collect::<Result<_>>()?,
+        })
+    }
+
+    /// A key is kept if it matches any `--only` pattern (or there are none) and no `--exclude` pattern
+    pub fn keeps(&self, key: &str) -> bool {
+        (self.only.is_empty() || self.only.iter().any(|re| re.is_match(key)))
+            && !self.exclude.iter().any(|re| re.is_match(key))
+    }
+
+    /// Splits entries into (kept, filtered out)
+    pub fn apply(&self, entries: Vec<EnvEntry>) -> (Vec<EnvEntry>, Vec<EnvEntry>) {
+        entries.into_iter().partition(|entry| self.keeps(&entry.key))
+    }
+}
+
+/// Kept computed entries that reference a filtered-out key, as (kept key, filtered-out key)
+pub fn broken_references(kept: &[EnvEntry], filtered_out: &[EnvEntry]) -> Vec<(String, String)> {
+    let renderer = TemplateRenderer::new();
+    let removed: HashSet<&str> = filtered_out.iter().map(|e| e.key.as_str()).collect();
+
+    let mut broken = Vec::new();
+    for entry in kept {
+        for var in renderer.references(entry.template().unwrap_or_default()) {
+            if removed.contains(var.name.as_str()) && !broken.contains(&(entry.key.clone(), var.name.clone())) {
+                broken.push((entry.key.clone(), var.name));
+            }
+        }
+    }
+    broken
+}
+
+fn compile_pattern(pattern: &str) -> Result<Regex> {
+    let source = match pattern.strip_prefix(REGEX_PREFIX) {
+        Some(regex) => regex.to_string(),
+        None => glob_to_regex(pattern),
+    };
+    Regex::new(&source).with_context(|| format!("Invalid key pattern: {}", pattern))
+}
+
+/// Translates a glob into an anchored regex: `*` matches any run of characters, `?` a single one
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(only: &[&str], exclude: &[&str]) -> KeyFilter {
+        let only: Vec<String> = only.iter().map(|s| s.to_string()).collect();
+        let exclude: Vec<String> = exclude.iter().map(|s| s.to_string()).collect();
+        KeyFilter::new(&only, &exclude).unwrap()
+    }
+
+    #[test]
+    fn test_glob_and_regex_patterns() {
+        let f = filter(&["COMMON_*", "re:^API_(KEY|URL)$"], &["*_READONLY_*"]);
+
+        assert!(f.keeps("COMMON_REGION"));
+        assert!(f.keeps("API_KEY"));
+        assert!(!f.keeps("API_KEYS"));
+        assert!(!f.keeps("DB_HOST"));
+        assert!(!f.keeps("COMMON_READONLY_TOKEN"));
+    }
+
+    #[test]
+    fn test_exact_names_and_empty_filter() {
+        assert!(filter(&[], &[]).keeps("ANYTHING"));
+
+        let f = filter(&[], &["DEBUG"]);
+        assert!(!f.keeps("DEBUG"));
+        assert!(f.keeps("DEBUG_LEVEL"));
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        let err = KeyFilter::new(&["re:(".to_string()], &[]).err().unwrap();
+        assert!(err.to_string().contains("Invalid key pattern: re:("));
+    }
+
+    #[test]
+    fn test_broken_references() {
+        let entries = vec![
+            EnvEntry::new("DB_PASSWORD", "", 1),
+            EnvEntry::new("APP_DB_URL", "postgres://app:${DB_PASSWORD}@${APP_HOST}", 2),
+            EnvEntry::new("APP_HOST", "localhost", 3),
+        ];
+        let (kept, filtered_out) = filter(&["APP_*"], &[]).apply(entries);
+
+        assert_eq!(kept.len(), 2);
+        assert_eq!(
+            broken_references(&kept, &filtered_out),
+            vec![("APP_DB_URL".to_string(), "DB_PASSWORD".to_string())]
+        );
+    }
+}
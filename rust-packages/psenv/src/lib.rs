@@ -1,3 +1,11 @@
+pub mod condition;
+pub mod diagnostic;
+pub mod filter;
+pub mod graph;
+pub mod key_filter;
+pub mod schema;
 pub mod secret_masker;
+pub mod structured_template;
 pub mod template_parser;
-pub mod template_renderer;
\ No newline at end of file
+pub mod template_renderer;
+pub mod template_scaffold;
\ No newline at end of file
@@ -1,27 +1,172 @@
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use log::{debug, error, info, warn};
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 mod aws_client;
+mod condition;
+mod diagnostic;
 mod env_handler;
+mod explain;
+mod filter;
+mod graph;
+mod key_filter;
+mod prompt;
+mod report;
+mod resolver;
+mod schema;
 pub mod secret_masker;
+mod shell;
+mod structured_template;
 mod template_parser;
-mod template_renderer;
+pub mod template_renderer;
+mod template_scaffold;
+mod watch;
 
 use aws_client::AwsClient;
-use env_handler::{EnvHandler, Strategy};
+use env_handler::{EnvHandler, FileAction, Strategy};
+use explain::Explainer;
+use prompt::Prompter;
+use report::{ReportFormat, RunReport};
+use graph::{DependencyGraph, GraphFormat};
+use key_filter::KeyFilter;
+use resolver::{MissingPolicy, Resolution, Resolver, Source};
+use schema::Schema;
 use secret_masker::SecretMasker;
-use template_parser::{EnvEntry, TemplateParser};
-use template_renderer::TemplateRenderer;
+use shell::Shell;
+use structured_template::TemplateFormat;
+use template_parser::{CommentedPolicy, DuplicatePolicy, EnvEntry, KeyGrammar, TemplateParser};
+use template_scaffold::TemplateScaffolder;
+use watch::{WatchOptions, Watcher};
 
 #[derive(Parser)]
 #[command(name = "psenv")]
 #[command(about = "AWS Parameter Store to .env tool")]
 #[command(version)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    fetch: Option<FetchArgs>,
+
+    #[arg(short, long, default_value = "false", global = true)]
+    #[arg(help = "Quiet mode")]
+    quiet: bool,
+
+    #[arg(short, long, default_value = "false", global = true)]
+    #[arg(help = "Verbose logging")]
+    verbose: bool,
+}
+
+/// Where to read the template and parameters from, shared by subcommands
+#[derive(Args)]
+struct SourceArgs {
+    #[arg(short, long)]
+    #[arg(help = "Template file path (e.g., .env.example), or - for stdin")]
+    template: String,
+
+    #[arg(short, long)]
+    #[arg(help = "Parameter Store prefix (must start with /)")]
+    prefix: String,
+
+    #[arg(short, long)]
+    #[arg(help = "AWS region")]
+    region: Option<String>,
+
+    #[arg(long)]
+    #[arg(help = "AWS profile")]
+    profile: Option<String>,
+
+    #[command(flatten)]
+    parser: ParserArgs,
+}
+
+/// How the template is read, shared by every command that parses one
+#[derive(Args)]
+struct ParserArgs {
+    #[arg(long, default_value = "upper")]
+    #[arg(help = "Which key names are recognized in the template and output file")]
+    key_grammar: KeyGrammar,
+
+    #[arg(long, default_value = "error")]
+    #[arg(help = "What to do when a key is defined more than once in the template")]
+    duplicates: DuplicatePolicy,
+
+    #[arg(long, default_value = "active")]
+    #[arg(help = "What to do with commented-out template entries (# KEY=value)")]
+    commented: CommentedPolicy,
+
+    #[arg(long, default_value = "auto")]
+    #[arg(help = "Template syntax; auto picks by file extension (.yaml/.yml, .json, .toml, else .env)")]
+    template_format: TemplateFormat,
+}
+
+impl ParserArgs {
+    fn parser(&self) -> TemplateParser {
+        TemplateParser::new()
+            .with_key_grammar(self.key_grammar)
+            .with_duplicates(self.duplicates)
+            .with_commented(self.commented)
+            .with_format(self.template_format)
+    }
+}
+
+/// Which keys are written and what happens when some are missing, shared by the default command and watch
+#[derive(Args)]
+struct SelectionArgs {
+    #[arg(short, long)]
+    #[arg(help = "Skip these keys (comma-separated)")]
+    ignore_keys: Option<String>,
+
+    #[arg(long)]
+    #[arg(help = "Only process keys matching this glob, or regex with re: prefix (repeatable)")]
+    only: Vec<String>,
+
+    #[arg(long)]
+    #[arg(help = "Skip keys matching this glob, or regex with re: prefix (repeatable)")]
+    exclude: Vec<String>,
+
+    #[arg(long, default_value = "true", num_args = 0..=1, default_missing_value = "true")]
+    #[arg(help = "Keys without @required/@optional are required (use --require-all=false to make them optional)")]
+    require_all: bool,
+
+    #[arg(long, default_value = "warn")]
+    #[arg(help = "What to do with optional keys that are missing")]
+    missing: MissingPolicy,
+}
+
+impl SelectionArgs {
+    /// `--only`, plus `--ignore-keys` and `--exclude` as one exclude list
+    fn key_filter(&self) -> Result<KeyFilter> {
+        // Parse ignore keys
+        let ignore_keys: Vec<String> = self.ignore_keys
+            .as_deref()
+            .unwrap_or("")
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| s.trim().to_string())
+            .collect();
+
+        debug!("Ignore keys: {:?}", ignore_keys);
+
+        // Ignored keys are exact names, which are also valid globs
+        let exclude: Vec<String> = ignore_keys.into_iter().chain(self.exclude.iter().cloned()).collect();
+        Ok(KeyFilter::new(&self.only, &exclude)
+            .map_err(|e| PsenvError::InvalidArguments(format!("{:#}", e)))?)
+    }
+}
+
+/// Arguments for the default command that writes the .env file
+#[derive(Args)]
+// clap leaves the group of a struct with a flattened field empty, so `Option<FetchArgs>` would
+// always be `None`; the required arguments are enough to tell that the command was given
+#[group(args = ["template", "prefix"])]
+struct FetchArgs {
     #[arg(short, long)]
-    #[arg(help = "Template file path (e.g., .env.example)")]
+    #[arg(help = "Template file path (e.g., .env.example), or - for stdin")]
     template: String,
 
     #[arg(short, long)]
@@ -29,20 +174,18 @@ struct Cli {
     prefix: String,
 
     #[arg(short, long, default_value = ".env")]
-    #[arg(help = "Output file (default: .env)")]
+    #[arg(help = "Output file (default: .env), or - for stdout")]
     output: String,
 
     #[arg(short, long, default_value = "overwrite")]
     #[arg(help = "Processing strategy")]
     strategy: Strategy,
 
-    #[arg(short, long)]
-    #[arg(help = "Skip these keys (comma-separated)")]
-    ignore_keys: Option<String>,
+    #[command(flatten)]
+    selection: SelectionArgs,
 
-    #[arg(long, default_value = "true")]
-    #[arg(help = "All keys must exist, otherwise error")]
-    require_all: bool,
+    #[command(flatten)]
+    parser: ParserArgs,
 
     #[arg(short, long)]
     #[arg(help = "AWS region")]
@@ -56,17 +199,148 @@ struct Cli {
     #[arg(help = "Preview mode")]
     dry_run: bool,
 
-    #[arg(short, long, default_value = "false")]
-    #[arg(help = "Quiet mode")]
-    quiet: bool,
-
-    #[arg(short, long, default_value = "false")]
-    #[arg(help = "Verbose logging")]
-    verbose: bool,
-
     #[arg(long, default_value = "false")]
     #[arg(help = "Show secrets in plaintext (default: mask sensitive values)")]
     show_secrets: bool,
+
+    #[arg(long, default_value = "false")]
+    #[arg(help = "Never prompt for missing values, even in a terminal")]
+    no_prompt: bool,
+
+    #[arg(long, default_value = "false", conflicts_with = "no_prompt")]
+    #[arg(help = "Save values entered at the prompt to Parameter Store under the prefix")]
+    save: bool,
+
+    #[arg(long)]
+    #[arg(help = "Print a machine-readable run report (never contains values)")]
+    report: Option<ReportFormat>,
+
+    #[arg(long, requires = "report")]
+    #[arg(help = "Write the report to this file instead of stdout")]
+    report_file: Option<String>,
+}
+
+/// How `psenv get` prints the value
+#[derive(Debug, Clone, ValueEnum)]
+enum ValueFormat {
+    /// The value as-is
+    #[value(name = "raw")]
+    Raw,
+    /// A JSON string
+    #[value(name = "json")]
+    Json,
+    /// Single-quoted for POSIX shells
+    #[value(name = "shell")]
+    Shell,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show where a key's value comes from and what it depends on
+    Explain {
+        #[arg(help = "Key to explain (e.g., DATABASE_URL)")]
+        key: String,
+
+        #[command(flatten)]
+        source: SourceArgs,
+
+        #[arg(long, default_value = "false")]
+        #[arg(help = "Show secrets in plaintext (default: mask sensitive values)")]
+        show_secrets: bool,
+    },
+
+    /// Print a single resolved value to stdout
+    Get {
+        #[arg(help = "Key to resolve (e.g., COMMON_OWEN_GH_TOKEN)")]
+        key: String,
+
+        #[command(flatten)]
+        source: SourceArgs,
+
+        #[arg(short, long, default_value = "raw")]
+        #[arg(help = "Output format")]
+        format: ValueFormat,
+    },
+
+    /// Print export statements to load the resolved variables into the current shell
+    Env {
+        #[command(flatten)]
+        source: SourceArgs,
+
+        #[arg(long, default_value = "bash")]
+        #[arg(help = "Shell to generate export statements for")]
+        shell: Shell,
+    },
+
+    /// Print the dependency graph between raw and computed variables
+    Graph {
+        #[arg(short, long)]
+        #[arg(help = "Template file path (e.g., .env.example), or - for stdin")]
+        template: String,
+
+        #[arg(short, long, default_value = "dot")]
+        #[arg(help = "Output format")]
+        format: GraphFormat,
+
+        #[command(flatten)]
+        parser: ParserArgs,
+    },
+
+    /// Scaffold a template from an existing .env file or Parameter Store prefix
+    Init {
+        #[arg(long, conflicts_with = "from_prefix", required_unless_present = "from_prefix")]
+        #[arg(help = "Existing .env file to read keys and values from")]
+        from_env: Option<String>,
+
+        #[arg(long)]
+        #[arg(help = "Parameter Store prefix to read keys and values from (must start with /)")]
+        from_prefix: Option<String>,
+
+        #[arg(short, long, default_value = ".env.example")]
+        #[arg(help = "Output template file (default: .env.example)")]
+        output: String,
+
+        #[arg(short, long, default_value = "error")]
+        #[arg(help = "Processing strategy")]
+        strategy: Strategy,
+
+        #[arg(long, default_value = "upper")]
+        #[arg(help = "Which key names are recognized in the template and output file")]
+        key_grammar: KeyGrammar,
+
+        #[arg(short, long)]
+        #[arg(help = "AWS region")]
+        region: Option<String>,
+
+        #[arg(long)]
+        #[arg(help = "AWS profile")]
+        profile: Option<String>,
+    },
+
+    /// Regenerate the output file whenever the template or remote values change
+    Watch {
+        #[command(flatten)]
+        source: SourceArgs,
+
+        #[arg(short, long, default_value = ".env")]
+        #[arg(help = "Output file (default: .env), or - for stdout")]
+        output: String,
+
+        #[arg(short, long, default_value = "overwrite")]
+        #[arg(help = "Processing strategy")]
+        strategy: Strategy,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
+
+        #[arg(long)]
+        #[arg(help = "Also poll Parameter Store every N seconds")]
+        poll_interval: Option<u64>,
+
+        #[arg(long)]
+        #[arg(help = "Shell command to run after the output file changes")]
+        exec: Option<String>,
+    },
 }
 
 #[tokio::main]
@@ -84,217 +358,375 @@ async fn main() {
 
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level)).init();
 
-    if let Err(e) = run(cli).await {
-        error!("Error: {}", e);
+    let result = match (cli.command, cli.fetch) {
+        (Some(Command::Explain { key, source, show_secrets }), _) => explain(&key, &source, show_secrets).await,
+        (Some(Command::Get { key, source, format }), _) => get(&key, &source, &format).await,
+        (Some(Command::Env { source, shell }), _) => env(&source, &shell).await,
+        (Some(Command::Graph { template, format, parser }), _) => graph(&template, &format, &parser.parser()),
+        (Some(Command::Init { from_env, from_prefix, output, strategy, key_grammar, region, profile }), _) => {
+            init(from_env, from_prefix, &output, strategy, key_grammar, region.as_deref(), profile.as_deref()).await
+        }
+        (Some(Command::Watch { source, output, strategy, selection, poll_interval, exec }), _) => {
+            watch(source, output, strategy, selection, poll_interval, exec).await
+        }
+        (None, Some(fetch)) => run(fetch).await,
+        // clap requires --template and --prefix when no subcommand is given
+        (None, None) => unreachable!("missing fetch arguments"),
+    };
+
+    if let Err(e) = result {
+        error!("Error: {:#}", e);
         let exit_code = match e.downcast_ref::<PsenvError>() {
             Some(PsenvError::InvalidArguments(_)) => 1,
             Some(PsenvError::RequiredParameterMissing(_)) => 3,
             Some(PsenvError::FileExists(_)) => 4,
+            Some(PsenvError::ValidationFailed(_)) => 5,
             _ => 1,
         };
         std::process::exit(exit_code);
     }
 }
 
-async fn run(cli: Cli) -> Result<()> {
+/// Template path that reads the template from stdin
+const STDIN: &str = "-";
+
+/// Parses the template file, or stdin for `-`
+fn parse_template(parser: &TemplateParser, template: &str) -> Result<Vec<EnvEntry>> {
+    if template == STDIN {
+        parser.parse_reader(std::io::stdin().lock()).context("Failed to parse template from stdin")
+    } else {
+        parser.parse_template(template).with_context(|| format!("Failed to parse template file: {}", template))
+    }
+}
+
+async fn run(args: FetchArgs) -> Result<()> {
     // Validate prefix
-    if !cli.prefix.starts_with('/') {
+    if !args.prefix.starts_with('/') {
         return Err(PsenvError::InvalidArguments("Prefix must start with '/'".to_string()).into());
     }
 
+    // The report would end up mixed in with the preview or the .env lines
+    if args.report.is_some() && args.report_file.is_none() && (args.dry_run || args.output == env_handler::STDOUT) {
+        return Err(PsenvError::InvalidArguments(
+            "--report prints to stdout, which --dry-run and -o - already use; add --report-file".to_string(),
+        ).into());
+    }
+
+    let started = Instant::now();
     debug!("Starting psenv with template: {}, prefix: {}, output: {}",
-           cli.template, cli.prefix, cli.output);
-
-    // Parse ignore keys
-    let ignore_keys: Vec<String> = cli.ignore_keys
-        .as_deref()
-        .unwrap_or("")
-        .split(',')
-        .filter(|s| !s.trim().is_empty())
-        .map(|s| s.trim().to_string())
-        .collect();
+           args.template, args.prefix, args.output);
 
-    debug!("Ignore keys: {:?}", ignore_keys);
+    let key_filter = args.selection.key_filter()?;
 
     // Parse template file to get entries with values
-    let parser = TemplateParser::new();
-    let entries = parser.parse_template(&cli.template)
-        .with_context(|| format!("Failed to parse template file: {}", cli.template))?;
+    let entries = parse_template(&args.parser.parser(), &args.template)?;
 
     info!("Found {} entries in template", entries.len());
 
-    // Filter out ignored keys
-    let filtered_entries: Vec<EnvEntry> = entries.into_iter()
-        .filter(|entry| !ignore_keys.contains(&entry.key))
-        .collect();
+    // Filter out ignored and excluded keys
+    let (filtered_entries, filtered_out) = key_filter.apply(entries);
+    for (key, dependency) in key_filter::broken_references(&filtered_entries, &filtered_out) {
+        warn!("{} depends on {}, which is filtered out", key, dependency);
+    }
 
     info!("Processing {} entries after filtering", filtered_entries.len());
 
+    // Check constraint declarations before fetching anything
+    let schema = Schema::from_entries(&filtered_entries)
+        .map_err(|e| PsenvError::InvalidArguments(format!("{:#}", e)))?;
+
     // Initialize AWS client
-    let aws_client = AwsClient::new(cli.region.as_deref(), cli.profile.as_deref()).await
+    let aws_client = AwsClient::new(args.region.as_deref(), args.profile.as_deref()).await
         .with_context(|| "Failed to initialize AWS client")?;
 
-    // Initialize template renderer
-    let renderer = TemplateRenderer::new();
-
-    // === PHASE 1: Resolve Raw Variables (Source Variables) ===
-    info!("Phase 1: Resolving raw variables...");
-    let mut context: HashMap<String, String> = HashMap::new();
-    let mut missing_keys = Vec::new();
-
-    for entry in &filtered_entries {
-        // Check if this is a raw variable (no template syntax)
-        if !renderer.contains_variables(&entry.raw_value) {
-            debug!("Processing raw variable: {}", entry.key);
-
-            // Priority: 1. AWS Parameter Store -> 2. Shell Env -> 3. .env.example literal
-            // AWS Parameter Store is the primary source - that's the whole point of psenv!
-            let param_path = format!("{}{}", cli.prefix, entry.key);
-            let value = match aws_client.get_parameter(&param_path).await {
-                Ok(Some(aws_val)) => {
-                    debug!("  ✓ Found in AWS Parameter Store");
-                    aws_val
-                }
-                Ok(None) => {
-                    // Not in AWS, try shell environment
-                    if let Ok(env_val) = std::env::var(&entry.key) {
-                        debug!("  ✓ Found in shell environment");
-                        env_val
-                    } else if !entry.raw_value.is_empty() {
-                        // Use literal from .env.example
-                        debug!("  ✓ Using literal default from template");
-                        entry.raw_value.clone()
-                    } else {
-                        debug!("  ✗ Not found in any source");
-                        missing_keys.push(entry.key.clone());
-                        continue;
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to fetch {}: {}. Trying shell env or literal default.", param_path, e);
-                    if let Ok(env_val) = std::env::var(&entry.key) {
-                        debug!("  ✓ Fallback to shell environment");
-                        env_val
-                    } else if !entry.raw_value.is_empty() {
-                        entry.raw_value.clone()
-                    } else {
-                        missing_keys.push(entry.key.clone());
-                        continue;
-                    }
-                }
-            };
+    // Resolve raw variables (Phase 1)
+    let resolver = Resolver::new();
+    let phase1_started = Instant::now();
+    let mut resolution = resolver.resolve_raw(&filtered_entries, &aws_client, &args.prefix).await;
+    let phase1 = phase1_started.elapsed();
 
-            context.insert(entry.key.clone(), value);
-        }
+    // Ask for missing raw values when a user is at the terminal
+    if !resolution.missing_keys.is_empty() && !args.no_prompt && Prompter::is_interactive() {
+        prompt_missing(&mut resolution, &filtered_entries, &aws_client, &args).await?;
     }
 
-    info!("Phase 1 complete: {} raw variables resolved", context.len());
+    // Render computed variables (Phase 2)
+    let phase2_started = Instant::now();
+    resolver.render_computed(&filtered_entries, &mut resolution);
+    let phase2 = phase2_started.elapsed();
 
-    // === PHASE 2: Render Computed Variables (Template Variables) ===
-    // Use iterative rendering to handle dependencies between computed variables
-    info!("Phase 2: Rendering computed variables...");
-    let mut unrendered: Vec<&EnvEntry> = filtered_entries.iter()
-        .filter(|e| renderer.contains_variables(&e.raw_value))
-        .collect();
-
-    let max_iterations = 10;
-    let mut iteration = 0;
-
-    while !unrendered.is_empty() && iteration < max_iterations {
-        iteration += 1;
-        debug!("Render iteration {}: {} variables remaining", iteration, unrendered.len());
-
-        let mut newly_rendered = Vec::new();
-
-        for (idx, entry) in unrendered.iter().enumerate() {
-            match renderer.render(&entry.raw_value, &context) {
-                Ok(rendered) => {
-                    debug!("  ✓ {} = {}", entry.key, rendered);
-                    context.insert(entry.key.clone(), rendered);
-                    newly_rendered.push(idx);
-                }
-                Err(_) => {
-                    // Can't render yet, might depend on other variables
-                    debug!("  ⏸ {} (waiting for dependencies)", entry.key);
-                }
-            }
-        }
-
-        // Remove successfully rendered entries
-        if newly_rendered.is_empty() {
-            // No progress made, remaining variables have unresolvable dependencies
-            break;
-        }
+    let result = write_output(&args, &filtered_entries, &schema, &mut resolution);
 
-        // Remove in reverse order to maintain indices
-        for &idx in newly_rendered.iter().rev() {
-            unrendered.remove(idx);
-        }
-    }
-
-    // Check for any remaining unrendered variables
-    let mut render_errors = Vec::new();
-    for entry in unrendered {
-        match renderer.render(&entry.raw_value, &context) {
-            Err(e) => {
-                error!("  ✗ Failed to render {}: {}", entry.key, e);
-                render_errors.push(format!("{}: {}", entry.key, e));
-            }
-            Ok(_) => {
-                // Shouldn't happen, but just in case
-                warn!("  ⚠ Variable {} was renderable but not rendered in iterations", entry.key);
-            }
-        }
+    if let Some(format) = &args.report {
+        let mut report = RunReport::new(&args.template, &args.prefix, &args.output, &filtered_entries, &resolution)
+            .with_timings(phase1, phase2, started.elapsed());
+        report.success = result.is_ok();
+        report.output_action = result.as_ref().ok().copied().flatten();
+        report.write(format, args.report_file.as_deref())?;
     }
 
-    info!("Phase 2 complete: {} total variables in context (rendered in {} iterations)",
-          context.len(), iteration);
+    result.map(|_| ())
+}
 
+/// Checks the resolution and writes (or previews) the output file
+fn write_output(
+    args: &FetchArgs,
+    entries: &[EnvEntry],
+    schema: &Schema,
+    resolution: &mut Resolution,
+) -> Result<Option<FileAction>> {
     // Check for errors
-    if cli.require_all {
-        if !missing_keys.is_empty() {
-            return Err(PsenvError::RequiredParameterMissing(
-                format!("Missing required raw variables: {}", missing_keys.join(", "))
-            ).into());
-        }
-        if !render_errors.is_empty() {
-            return Err(PsenvError::RequiredParameterMissing(
-                format!("Failed to render computed variables:\n{}", render_errors.join("\n"))
-            ).into());
-        }
-    } else {
-        if !missing_keys.is_empty() {
-            warn!("Missing raw variables: {}", missing_keys.join(", "));
-        }
-        if !render_errors.is_empty() {
-            warn!("Failed to render some computed variables:\n{}", render_errors.join("\n"));
-        }
-    }
+    resolution.enforce(entries, args.selection.require_all, args.selection.missing)?;
+    validate(entries, schema, resolution, args.show_secrets)?;
+    let context = &resolution.context;
 
     // Handle .env file generation
-    let env_handler = EnvHandler::new();
+    let env_handler = EnvHandler::new().with_key_grammar(args.parser.key_grammar);
 
-    if cli.dry_run {
-        info!("Dry run mode - would write to: {}", cli.output);
-        let masker = SecretMasker::new();
+    if args.dry_run {
+        info!("Dry run mode - would write to: {}", args.output);
+        let masker = SecretMasker::new().with_entries(entries);
         let mut sorted_keys: Vec<&String> = context.keys().collect();
         sorted_keys.sort();
 
         for key in sorted_keys {
             if let Some(value) = context.get(key) {
-                println!("{}", masker.format_output(key, value, cli.show_secrets));
+                println!("{}", masker.format_output(key, value, args.show_secrets));
             }
         }
+        Ok(None)
     } else {
-        env_handler.handle_env_file(&cli.output, &context, cli.strategy)
-            .with_context(|| format!("Failed to handle .env file: {}", cli.output))?;
+        let action = env_handler.handle_env_file(&args.output, context, args.strategy.clone())
+            .with_context(|| format!("Failed to handle .env file: {}", args.output))?;
+
+        info!("Successfully updated {}", args.output);
+        Ok(Some(action))
+    }
+}
+
+/// Checks resolved values against the template constraints, listing every violation
+fn validate(entries: &[EnvEntry], schema: &Schema, resolution: &Resolution, show_secrets: bool) -> Result<()> {
+    let values = resolution.values_to_validate();
+    let violations = schema.validate(&values);
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    let masker = SecretMasker::new().with_entries(entries);
+    let lines: Vec<String> = violations.iter()
+        .map(|violation| {
+            let key = &violation.key;
+            let value = &values[key];
+            let shown = if show_secrets || !resolution.is_sensitive(key, &masker) {
+                format!("{:?}", value)
+            } else {
+                masker.mask_value(value)
+            };
+            let source = resolution.sources.get(key).map(|s| s.describe(key)).unwrap_or_default();
+            format!("  {}: {}, got {} [{}]", key, violation.message, shown, source)
+        })
+        .collect();
+
+    Err(PsenvError::ValidationFailed(lines.join("\n")).into())
+}
 
-        info!("Successfully updated {}", cli.output);
+/// Prompts for missing raw values and optionally saves them under the prefix
+async fn prompt_missing(
+    resolution: &mut Resolution,
+    entries: &[EnvEntry],
+    aws_client: &AwsClient,
+    args: &FetchArgs,
+) -> Result<()> {
+    let missing_keys: Vec<String> = resolution.missing_keys.iter().map(|m| m.key.clone()).collect();
+    let paths: HashMap<String, String> = resolution.missing_keys.iter()
+        .map(|m| (m.key.clone(), m.ssm_path.clone()))
+        .collect();
+    let entered = Prompter::new().with_entries(entries).prompt_missing(&missing_keys)?;
+    let masker = SecretMasker::new().with_entries(entries);
+
+    let mut saved_keys = Vec::new();
+    for (key, value) in entered {
+        if args.save && !args.dry_run {
+            aws_client.put_parameter(&paths[&key], &value, masker.is_sensitive_key(&key)).await?;
+            saved_keys.push(key.clone());
+        }
+        resolution.missing_keys.retain(|m| m.key != key);
+        resolution.context.insert(key.clone(), value);
+        resolution.sources.insert(key, Source::Prompt);
     }
 
+    if !saved_keys.is_empty() {
+        saved_keys.sort();
+        info!("Saved to Parameter Store under {}: {}", args.prefix, saved_keys.join(", "));
+    } else if args.save && args.dry_run {
+        info!("Dry run mode - not saving entered values to Parameter Store");
+    }
     Ok(())
 }
 
+async fn explain(key: &str, source: &SourceArgs, show_secrets: bool) -> Result<()> {
+    if !source.prefix.starts_with('/') {
+        return Err(PsenvError::InvalidArguments("Prefix must start with '/'".to_string()).into());
+    }
+
+    let entries = parse_template(&source.parser.parser(), &source.template)?;
+
+    if !entries.iter().any(|e| e.key == key) {
+        return Err(PsenvError::InvalidArguments(
+            format!("Key {} is not defined in template: {}", key, source.template)
+        ).into());
+    }
+
+    let aws_client = AwsClient::new(source.region.as_deref(), source.profile.as_deref()).await
+        .with_context(|| "Failed to initialize AWS client")?;
+
+    let resolution = Resolver::new().resolve(&entries, &aws_client, &source.prefix).await;
+
+    print!("{}", Explainer::new(&resolution, &entries, &source.prefix, show_secrets).explain(key));
+    Ok(())
+}
+
+async fn get(key: &str, source: &SourceArgs, format: &ValueFormat) -> Result<()> {
+    if !source.prefix.starts_with('/') {
+        return Err(PsenvError::InvalidArguments("Prefix must start with '/'".to_string()).into());
+    }
+
+    let entries = parse_template(&source.parser.parser(), &source.template)?;
+
+    if !entries.iter().any(|e| e.key == key) {
+        return Err(PsenvError::InvalidArguments(
+            format!("Key {} is not defined in template: {}", key, source.template)
+        ).into());
+    }
+
+    let aws_client = AwsClient::new(source.region.as_deref(), source.profile.as_deref()).await
+        .with_context(|| "Failed to initialize AWS client")?;
+
+    // Only fetch what this key depends on
+    let resolver = Resolver::new();
+    let needed = resolver.dependencies_of(&entries, key);
+    let resolution = resolver.resolve(&needed, &aws_client, &source.prefix).await;
+
+    let Some(value) = resolution.context.get(key) else {
+        return Err(PsenvError::RequiredParameterMissing(format!("Could not resolve {}", key)).into());
+    };
+
+    match format {
+        ValueFormat::Raw => println!("{}", value),
+        ValueFormat::Json => println!("{}", serde_json::to_string(value)?),
+        ValueFormat::Shell => println!("{}", shell::posix_quote(value)),
+    }
+    Ok(())
+}
+
+async fn env(source: &SourceArgs, shell: &Shell) -> Result<()> {
+    if !source.prefix.starts_with('/') {
+        return Err(PsenvError::InvalidArguments("Prefix must start with '/'".to_string()).into());
+    }
+
+    let entries = parse_template(&source.parser.parser(), &source.template)?;
+    let schema = Schema::from_entries(&entries)
+        .map_err(|e| PsenvError::InvalidArguments(format!("{:#}", e)))?;
+
+    let aws_client = AwsClient::new(source.region.as_deref(), source.profile.as_deref()).await
+        .with_context(|| "Failed to initialize AWS client")?;
+
+    let mut resolution = Resolver::new().resolve(&entries, &aws_client, &source.prefix).await;
+    resolution.enforce(&entries, true, MissingPolicy::Warn)?;
+    validate(&entries, &schema, &resolution, false)?;
+
+    print!("{}", shell.export_script(&resolution.context));
+    Ok(())
+}
+
+fn graph(template: &str, format: &GraphFormat, parser: &TemplateParser) -> Result<()> {
+    let entries = parse_template(parser, template)?;
+
+    print!("{}", DependencyGraph::build(&entries).render(format)?);
+    Ok(())
+}
+
+async fn init(
+    from_env: Option<String>,
+    from_prefix: Option<String>,
+    output: &str,
+    strategy: Strategy,
+    key_grammar: KeyGrammar,
+    region: Option<&str>,
+    profile: Option<&str>,
+) -> Result<()> {
+    let values: HashMap<String, String> = match (from_env, from_prefix) {
+        // Like dotenv loaders, the last definition in an existing .env file wins and commented lines are skipped
+        (Some(env_file), _) => TemplateParser::new()
+            .with_key_grammar(key_grammar)
+            .with_duplicates(DuplicatePolicy::KeepLast)
+            .with_commented(CommentedPolicy::Ignore)
+            .parse_template(&env_file)
+            .with_context(|| format!("Failed to parse .env file: {}", env_file))?
+            .into_iter()
+            .map(|entry| (entry.key, entry.raw_value))
+            .collect(),
+        (None, Some(prefix)) => {
+            if !prefix.starts_with('/') {
+                return Err(PsenvError::InvalidArguments("Prefix must start with '/'".to_string()).into());
+            }
+            let aws_client = AwsClient::new(region, profile).await
+                .with_context(|| "Failed to initialize AWS client")?;
+            aws_client.get_parameters_by_path(&prefix).await?.into_iter().collect()
+        }
+        // clap requires exactly one of --from-env and --from-prefix
+        (None, None) => unreachable!("missing init source"),
+    };
+
+    info!("Found {} keys", values.len());
+
+    let template = TemplateScaffolder::new().scaffold(&values);
+    EnvHandler::new().with_key_grammar(key_grammar).handle_env_file(output, &template, strategy)
+        .with_context(|| format!("Failed to write template file: {}", output))?;
+
+    info!("Successfully wrote template {}", output);
+    Ok(())
+}
+
+async fn watch(
+    source: SourceArgs,
+    output: String,
+    strategy: Strategy,
+    selection: SelectionArgs,
+    poll_interval: Option<u64>,
+    exec: Option<String>,
+) -> Result<()> {
+    if !source.prefix.starts_with('/') {
+        return Err(PsenvError::InvalidArguments("Prefix must start with '/'".to_string()).into());
+    }
+    if poll_interval == Some(0) {
+        return Err(PsenvError::InvalidArguments("Poll interval must be at least 1 second".to_string()).into());
+    }
+    if source.template == STDIN {
+        return Err(PsenvError::InvalidArguments("Watch needs a template file, stdin can't be watched".to_string()).into());
+    }
+    let key_filter = selection.key_filter()?;
+
+    let aws_client = AwsClient::new(source.region.as_deref(), source.profile.as_deref()).await
+        .with_context(|| "Failed to initialize AWS client")?;
+
+    let options = WatchOptions {
+        template: source.template,
+        prefix: source.prefix,
+        output,
+        strategy,
+        poll_interval: poll_interval.map(Duration::from_secs),
+        exec,
+        key_filter,
+        require_all: selection.require_all,
+        missing: selection.missing,
+        parser: source.parser.parser(),
+        env_handler: EnvHandler::new().with_key_grammar(source.parser.key_grammar),
+    };
+
+    Watcher::new(options, &aws_client).run().await
+}
+
 #[derive(Debug, thiserror::Error)]
 enum PsenvError {
     #[error("Invalid arguments: {0}")]
@@ -305,4 +737,7 @@ enum PsenvError {
 
     #[error("File exists: {0}")]
     FileExists(String),
+
+    #[error("Invalid values:\n{0}")]
+    ValidationFailed(String),
 }
\ No newline at end of file
@@ -0,0 +1,74 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use crate::secret_masker::SecretMasker;
+use crate::template_parser::EnvEntry;
+
+/// Asks the user for missing raw values on the terminal
+pub struct Prompter {
+    masker: SecretMasker,
+    /// `@description` of each key, shown next to its prompt
+    descriptions: HashMap<String, String>,
+}
+
+impl Prompter {
+    pub fn new() -> Self {
+        Prompter {
+            masker: SecretMasker::new(),
+            descriptions: HashMap::new(),
+        }
+    }
+
+    /// Uses the template annotations: `@secret` / `@public` decide which input is hidden
+    pub fn with_entries(mut self, entries: &[EnvEntry]) -> Self {
+        self.masker = self.masker.with_entries(entries);
+        self.descriptions = entries.iter()
+            .filter_map(|e| e.description.clone().map(|d| (e.key.clone(), d)))
+            .collect();
+        self
+    }
+
+    /// Prompting only makes sense when a user is at the terminal
+    pub fn is_interactive() -> bool {
+        io::stdin().is_terminal() && io::stderr().is_terminal()
+    }
+
+    /// Prompts for each key, using hidden input for sensitive keys
+    ///
+    /// Keys left empty are skipped and not part of the result.
+    pub fn prompt_missing(&self, keys: &[String]) -> Result<HashMap<String, String>> {
+        let mut values = HashMap::new();
+        eprintln!("{} value(s) missing, press Enter to skip:", keys.len());
+
+        for key in keys {
+            let label = match self.descriptions.get(key) {
+                Some(description) => format!("{} ({})", key, description),
+                None => key.clone(),
+            };
+            let value = if self.masker.is_sensitive_key(key) {
+                rpassword::prompt_password(format!("  {} (hidden): ", label))
+                    .with_context(|| format!("Failed to read value for {}", key))?
+            } else {
+                eprint!("  {}: ", label);
+                io::stderr().flush()?;
+                let mut line = String::new();
+                io::stdin().lock().read_line(&mut line)
+                    .with_context(|| format!("Failed to read value for {}", key))?;
+                line.trim_end_matches(['\r', '\n']).to_string()
+            };
+
+            if !value.is_empty() {
+                values.insert(key.clone(), value);
+            }
+        }
+
+        Ok(values)
+    }
+}
+
+impl Default for Prompter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,235 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs;
+use std::time::Duration;
+
+use crate::env_handler::FileAction;
+use crate::resolver::{MissingKey, Resolution, Source};
+use crate::secret_masker::SecretMasker;
+use crate::template_parser::EnvEntry;
+use crate::template_renderer::RefSource;
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum ReportFormat {
+    #[value(name = "json")]
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    Ssm,
+    Env,
+    Literal,
+    /// Computed, but every reference fell back to its `${VAR:-default}`, or set by `${VAR:=default}`
+    Default,
+    Computed,
+    Prompt,
+    /// Optional key written empty by `--missing write-empty`
+    Empty,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyReport {
+    pub key: String,
+    pub source: KeySource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssm_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssm_version: Option<i64>,
+    /// Template file that defines the key, which may be an included file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// Declared or detected as sensitive, directly or through the keys it is computed from
+    pub sensitive: bool,
+    /// From the `@description` annotation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RenderErrorReport {
+    pub key: String,
+    pub error: String,
+    /// Where in the template the error is, e.g. `.env.example:4:14`
+    pub location: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Timings {
+    pub phase1_ms: u128,
+    pub phase2_ms: u128,
+    pub total_ms: u128,
+}
+
+/// Machine-readable summary of a run, safe to publish: no values are included, only where they came from
+#[derive(Debug, Serialize)]
+pub struct RunReport {
+    pub success: bool,
+    pub template: String,
+    pub prefix: String,
+    pub output: String,
+    pub keys: Vec<KeyReport>,
+    pub missing_keys: Vec<MissingKey>,
+    pub render_errors: Vec<RenderErrorReport>,
+    pub iterations: usize,
+    pub timings: Timings,
+    /// `None` when nothing was written (dry run or failure)
+    pub output_action: Option<FileAction>,
+}
+
+impl RunReport {
+    pub fn new(template: &str, prefix: &str, output: &str, entries: &[EnvEntry], resolution: &Resolution) -> Self {
+        let masker = SecretMasker::new().with_entries(entries);
+
+        let mut keys: Vec<KeyReport> = resolution.context.keys()
+            .map(|key| {
+                let mut report = KeyReport {
+                    key: key.clone(),
+                    source: KeySource::Computed,
+                    ssm_path: None,
+                    ssm_version: None,
+                    file: entries.iter().find(|e| &e.key == key).and_then(|e| e.file.clone()),
+                    line: None,
+                    sensitive: resolution.is_sensitive(key, &masker),
+                    description: entries.iter().find(|e| &e.key == key).and_then(|e| e.description.clone()),
+                };
+                match resolution.sources.get(key) {
+                    Some(Source::Ssm { path, version }) => {
+                        report.source = KeySource::Ssm;
+                        report.ssm_path = Some(path.clone());
+                        report.ssm_version = Some(*version);
+                    }
+                    Some(Source::Env) => report.source = KeySource::Env,
+                    Some(Source::Literal { line }) => {
+                        report.source = KeySource::Literal;
+                        report.line = Some(*line);
+                    }
+                    Some(Source::Prompt) => report.source = KeySource::Prompt,
+                    Some(Source::Empty) => report.source = KeySource::Empty,
+                    Some(Source::Computed { line, refs, .. }) => {
+                        if !refs.is_empty() && refs.iter().all(|r| r.source == RefSource::Default) {
+                            report.source = KeySource::Default;
+                        }
+                        report.line = Some(*line);
+                    }
+                    Some(Source::Assigned { line, .. }) => {
+                        report.source = KeySource::Default;
+                        report.line = Some(*line);
+                    }
+                    None => {}
+                }
+                report
+            })
+            .collect();
+        keys.sort_by(|a, b| a.key.cmp(&b.key));
+
+        RunReport {
+            success: resolution.missing_keys.is_empty() && resolution.render_errors.is_empty(),
+            template: template.to_string(),
+            prefix: prefix.to_string(),
+            output: output.to_string(),
+            keys,
+            missing_keys: resolution.missing_keys.clone(),
+            render_errors: resolution.render_errors.iter()
+                .map(|(key, diagnostic)| RenderErrorReport {
+                    key: key.clone(),
+                    error: diagnostic.message.clone(),
+                    location: diagnostic.location(),
+                    hint: diagnostic.hint.clone(),
+                })
+                .collect(),
+            iterations: resolution.iterations,
+            timings: Timings::default(),
+            output_action: None,
+        }
+    }
+
+    pub fn with_timings(mut self, phase1: Duration, phase2: Duration, total: Duration) -> Self {
+        self.timings = Timings {
+            phase1_ms: phase1.as_millis(),
+            phase2_ms: phase2.as_millis(),
+            total_ms: total.as_millis(),
+        };
+        self
+    }
+
+    /// Writes the report to `path`, or to stdout when no path is given
+    pub fn write(&self, format: &ReportFormat, path: Option<&str>) -> Result<()> {
+        let content = match format {
+            ReportFormat::Json => serde_json::to_string_pretty(self)? + "\n",
+        };
+
+        match path {
+            Some(path) => fs::write(path, content)
+                .with_context(|| format!("Failed to write report file: {}", path)),
+            None => {
+                print!("{}", content);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::Resolver;
+    use crate::template_parser::EnvEntry;
+
+    fn entry(key: &str, raw_value: &str, line: usize) -> EnvEntry {
+        EnvEntry::new(key, raw_value, line)
+    }
+
+    #[test]
+    fn test_report_never_contains_secrets() {
+        let mut entries = vec![
+            entry("PG_PASSWORD", "", 1),
+            entry("PG_PORT", "5432", 2),
+            entry("PG_HOST", "${PSENV_TEST_UNSET_CTX_HOST:-localhost}", 3),
+            entry("DATABASE_URL", "postgresql://app:${PG_PASSWORD}@${PG_HOST}:${PG_PORT}/app", 4),
+            entry("CALLBACK_URL", "${PSENV_TEST_UNSET_APP_URL}/cb", 5),
+        ];
+        entries[1].description = Some("Postgres port".to_string());
+
+        let mut resolution = Resolution::default();
+        resolution.context.insert("PG_PASSWORD".to_string(), "hunter2".to_string());
+        resolution.sources.insert(
+            "PG_PASSWORD".to_string(),
+            Source::Ssm { path: "/studio-dev/PG_PASSWORD".to_string(), version: 7 },
+        );
+        resolution.context.insert("PG_PORT".to_string(), "5432".to_string());
+        resolution.sources.insert("PG_PORT".to_string(), Source::Literal { line: 2 });
+        resolution.missing_keys.push(MissingKey {
+            key: "API_TOKEN".to_string(),
+            ssm_path: "/studio-dev/API_TOKEN".to_string(),
+        });
+        Resolver::new().render_computed(&entries, &mut resolution);
+
+        let report = RunReport::new(".env.example", "/studio-dev/", ".env", &entries, &resolution);
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(!json.contains("hunter2"));
+        // Values of keys that aren't flagged as sensitive can embed secrets too, so none are included
+        assert!(!json.contains("5432"));
+        assert!(!report.success);
+
+        let key = |name: &str| report.keys.iter().find(|k| k.key == name).unwrap();
+        assert_eq!(key("PG_PASSWORD").source, KeySource::Ssm);
+        assert_eq!(key("PG_PASSWORD").ssm_version, Some(7));
+        assert!(key("PG_PASSWORD").sensitive);
+        assert_eq!(key("PG_PORT").source, KeySource::Literal);
+        assert!(!key("PG_PORT").sensitive);
+        assert_eq!(key("PG_PORT").description.as_deref(), Some("Postgres port"));
+        assert_eq!(key("PG_HOST").source, KeySource::Default);
+        assert_eq!(key("DATABASE_URL").source, KeySource::Computed);
+        assert!(key("DATABASE_URL").sensitive);
+
+        assert_eq!(report.missing_keys[0].key, "API_TOKEN");
+        assert_eq!(report.render_errors[0].key, "CALLBACK_URL");
+    }
+}
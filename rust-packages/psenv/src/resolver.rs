@@ -0,0 +1,570 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::aws_client::AwsClient;
+use crate::diagnostic::{closest_match, Diagnostic};
+use crate::secret_masker::SecretMasker;
+use crate::template_parser::EnvEntry;
+use crate::template_renderer::{Expansion, RefSource, ResolvedRef, TemplateRenderer};
+use crate::PsenvError;
+
+/// Where a resolved value came from
+#[derive(Debug, Clone)]
+pub enum Source {
+    /// Fetched from AWS Parameter Store
+    Ssm { path: String, version: i64 },
+    /// Taken from the shell environment
+    Env,
+    /// Literal value from the template line
+    Literal { line: usize },
+    /// Entered interactively by the user
+    Prompt,
+    /// Optional key left empty by `--missing write-empty`
+    Empty,
+    /// Rendered from a template expression
+    Computed { line: usize, template: String, refs: Vec<ResolvedRef> },
+    /// Set by a `${KEY:=default}` expansion on another template line
+    Assigned { line: usize, expression: String },
+}
+
+impl Source {
+    /// Short human-readable description, e.g. `ssm /app/PORT (version 3)`
+    pub fn describe(&self, key: &str) -> String {
+        match self {
+            Source::Ssm { path, version } => format!("ssm {} (version {})", path, version),
+            Source::Env => format!("shell env {}", key),
+            Source::Literal { line } => format!("template literal, line {}", line),
+            Source::Prompt => "entered at prompt".to_string(),
+            Source::Empty => "optional, written empty".to_string(),
+            Source::Computed { line, .. } => format!("computed, template line {}", line),
+            Source::Assigned { line, expression } => format!("assigned by {}, template line {}", expression, line),
+        }
+    }
+}
+
+/// A raw variable that was not found in any source
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MissingKey {
+    pub key: String,
+    /// Parameter Store path that was tried
+    pub ssm_path: String,
+}
+
+/// What to do with optional keys that could not be resolved
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum MissingPolicy {
+    /// Fail, same as for required keys
+    #[value(name = "error")]
+    Error,
+    /// Log a warning and leave the key out
+    #[value(name = "warn")]
+    Warn,
+    /// Silently leave the key out
+    #[value(name = "omit")]
+    Omit,
+    /// Write the key with an empty value
+    #[value(name = "write-empty")]
+    WriteEmpty,
+}
+
+/// Outcome of resolving a set of template entries
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub context: HashMap<String, String>,
+    pub sources: HashMap<String, Source>,
+    pub missing_keys: Vec<MissingKey>,
+    pub render_errors: Vec<(String, Diagnostic)>,
+    pub iterations: usize,
+}
+
+impl Resolution {
+    /// Applies required/optional semantics to missing and unrenderable keys
+    ///
+    /// Keys without `@required`/`@optional` are required when `require_all` is set.
+    /// Missing required keys always fail; optional ones are handled by `policy`.
+    pub fn enforce(&mut self, entries: &[EnvEntry], require_all: bool, policy: MissingPolicy) -> Result<()> {
+        let is_required = |key: &str| {
+            policy == MissingPolicy::Error
+                || entries.iter().find(|e| e.key == key).and_then(|e| e.required).unwrap_or(require_all)
+        };
+
+        let (required_missing, optional_missing): (Vec<_>, Vec<_>) =
+            self.missing_keys.iter().partition(|m| is_required(&m.key));
+        let (required_errors, optional_errors): (Vec<_>, Vec<_>) =
+            self.render_errors.iter().partition(|(key, _)| is_required(key));
+
+        if !required_missing.is_empty() || !required_errors.is_empty() {
+            let mut message = Vec::new();
+            if !required_missing.is_empty() {
+                message.push("Missing required raw variables:".to_string());
+                message.extend(required_missing.iter().map(|m| format!("  {} (tried {})", m.key, m.ssm_path)));
+            }
+            if !required_errors.is_empty() {
+                message.push("Failed to render computed variables:".to_string());
+                message.extend(required_errors.iter().map(|(_, diagnostic)| format!("\n{}", diagnostic)));
+            }
+            return Err(PsenvError::RequiredParameterMissing(message.join("\n")).into());
+        }
+
+        let optional_keys: Vec<String> = optional_missing.iter().map(|m| m.key.clone())
+            .chain(optional_errors.iter().map(|(key, _)| key.clone()))
+            .collect();
+        if optional_keys.is_empty() {
+            return Ok(());
+        }
+
+        match policy {
+            MissingPolicy::Error => unreachable!("all keys are required under the error policy"),
+            MissingPolicy::Warn => {
+                for missing in &optional_missing {
+                    warn!("Missing optional variable {} (tried {})", missing.key, missing.ssm_path);
+                }
+                for (key, err) in &optional_errors {
+                    warn!("Failed to render optional variable {}:\n{}", key, err);
+                }
+            }
+            MissingPolicy::Omit => {
+                debug!("Omitting optional variables: {}", optional_keys.join(", "));
+            }
+            MissingPolicy::WriteEmpty => {
+                info!("Writing empty values for optional variables: {}", optional_keys.join(", "));
+                for key in optional_keys {
+                    self.context.insert(key.clone(), String::new());
+                    self.sources.insert(key, Source::Empty);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Values the template constraints apply to
+    ///
+    /// Optional keys written empty by `--missing write-empty` have no value to check.
+    pub fn values_to_validate(&self) -> HashMap<String, String> {
+        self.context.iter()
+            .filter(|(key, _)| !matches!(self.sources.get(*key), Some(Source::Empty)))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// A key is sensitive if its name is, or if it is computed from a sensitive key
+    pub fn is_sensitive(&self, key: &str, masker: &SecretMasker) -> bool {
+        self.is_sensitive_inner(key, masker, &mut HashSet::new())
+    }
+
+    fn is_sensitive_inner(&self, key: &str, masker: &SecretMasker, visited: &mut HashSet<String>) -> bool {
+        // An explicit @secret/@public wins over what the key is computed from
+        if let Some(sensitive) = masker.declared_sensitivity(key) {
+            return sensitive;
+        }
+        if masker.is_sensitive_key(key) {
+            return true;
+        }
+        if !visited.insert(key.to_string()) {
+            return false;
+        }
+        match self.sources.get(key) {
+            Some(Source::Computed { refs, .. }) => {
+                refs.iter().any(|r| self.is_sensitive_inner(&r.var.name, masker, visited))
+            }
+            _ => false,
+        }
+    }
+}
+
+pub struct Resolver {
+    renderer: TemplateRenderer,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver {
+            renderer: TemplateRenderer::new(),
+        }
+    }
+
+    /// Runs both phases: raw variables first, then computed variables
+    pub async fn resolve(&self, entries: &[EnvEntry], aws_client: &AwsClient, prefix: &str) -> Resolution {
+        let mut resolution = self.resolve_raw(entries, aws_client, prefix).await;
+        self.render_computed(entries, &mut resolution);
+        resolution
+    }
+
+    /// Entries needed to resolve `key`: the entry itself and everything it references, transitively
+    pub fn dependencies_of(&self, entries: &[EnvEntry], key: &str) -> Vec<EnvEntry> {
+        let mut needed = HashSet::new();
+        let mut pending = vec![key.to_string()];
+
+        while let Some(name) = pending.pop() {
+            if !needed.insert(name.clone()) {
+                continue;
+            }
+            if let Some(entry) = entries.iter().find(|e| e.key == name) {
+                pending.extend(self.renderer.references(entry.template().unwrap_or_default()).into_iter().map(|var| var.name));
+            }
+        }
+
+        entries.iter().filter(|e| needed.contains(&e.key)).cloned().collect()
+    }
+
+    /// Phase 1: resolve raw variables (entries without template syntax)
+    pub async fn resolve_raw(&self, entries: &[EnvEntry], aws_client: &AwsClient, prefix: &str) -> Resolution {
+        info!("Phase 1: Resolving raw variables...");
+        let mut resolution = Resolution::default();
+
+        for entry in entries {
+            // Check if this is a raw variable (no template syntax, only escapes, or `@raw`)
+            if entry.template().is_some_and(|t| self.renderer.contains_variables(t)) {
+                continue;
+            }
+            debug!("Processing raw variable: {}", entry.key);
+
+            // Priority: 1. AWS Parameter Store -> 2. Shell Env -> 3. .env.example literal
+            // AWS Parameter Store is the primary source - that's the whole point of psenv!
+            let param_path = entry.parameter_path(prefix);
+            let resolved = match aws_client.get_parameter(&param_path).await {
+                Ok(Some(parameter)) => {
+                    debug!("  ✓ Found in AWS Parameter Store");
+                    Some((parameter.value, Source::Ssm { path: param_path.clone(), version: parameter.version }))
+                }
+                Ok(None) => self.resolve_local(entry),
+                Err(e) => {
+                    warn!("Failed to fetch {}: {}. Trying shell env or literal default.", param_path, e);
+                    self.resolve_local(entry)
+                }
+            };
+
+            match resolved {
+                Some((value, source)) => {
+                    resolution.context.insert(entry.key.clone(), value);
+                    resolution.sources.insert(entry.key.clone(), source);
+                }
+                None => {
+                    debug!("  ✗ Not found in any source");
+                    resolution.missing_keys.push(MissingKey {
+                        key: entry.key.clone(),
+                        ssm_path: param_path,
+                    });
+                }
+            }
+        }
+
+        info!("Phase 1 complete: {} raw variables resolved", resolution.context.len());
+        resolution
+    }
+
+    /// Falls back to the shell environment, then to the template literal
+    fn resolve_local(&self, entry: &EnvEntry) -> Option<(String, Source)> {
+        if let Ok(env_val) = std::env::var(&entry.key) {
+            debug!("  ✓ Found in shell environment");
+            Some((env_val, Source::Env))
+        } else if !entry.raw_value.is_empty() {
+            debug!("  ✓ Using literal default from template");
+            // `@raw` values are taken as written, others may still contain `$${` escapes
+            let value = match entry.template() {
+                Some(template) => self.renderer.unescape(template),
+                None => entry.raw_value.clone(),
+            };
+            Some((value, Source::Literal { line: entry.line }))
+        } else {
+            None
+        }
+    }
+
+    /// Phase 2: render computed variables (entries with template syntax)
+    ///
+    /// Uses iterative rendering to handle dependencies between computed variables.
+    pub fn render_computed(&self, entries: &[EnvEntry], resolution: &mut Resolution) {
+        info!("Phase 2: Rendering computed variables...");
+        let mut unrendered: Vec<&EnvEntry> = entries.iter()
+            .filter(|e| e.template().is_some_and(|t| self.renderer.contains_variables(t)))
+            .collect();
+
+        let max_iterations = 10;
+        let mut iteration = 0;
+
+        while !unrendered.is_empty() && iteration < max_iterations {
+            iteration += 1;
+            debug!("Render iteration {}: {} variables remaining", iteration, unrendered.len());
+
+            let mut newly_rendered = Vec::new();
+
+            for (idx, entry) in unrendered.iter().enumerate() {
+                match self.renderer.render_traced(&entry.raw_value, &resolution.context) {
+                    Ok((rendered, refs)) => {
+                        debug!("  ✓ {} = {}", entry.key, rendered);
+                        self.assign(entry, &refs, entries, &unrendered, resolution);
+                        resolution.context.insert(entry.key.clone(), rendered);
+                        resolution.sources.insert(entry.key.clone(), Source::Computed {
+                            line: entry.line,
+                            template: entry.raw_value.clone(),
+                            refs,
+                        });
+                        newly_rendered.push(idx);
+                    }
+                    Err(_) => {
+                        // Can't render yet, might depend on other variables
+                        debug!("  ⏸ {} (waiting for dependencies)", entry.key);
+                    }
+                }
+            }
+
+            // No progress made, remaining variables have unresolvable dependencies
+            if newly_rendered.is_empty() {
+                break;
+            }
+
+            // Remove in reverse order to maintain indices
+            for &idx in newly_rendered.iter().rev() {
+                unrendered.remove(idx);
+            }
+        }
+
+        // Check for any remaining unrendered variables
+        for entry in unrendered {
+            match self.renderer.render_traced(&entry.raw_value, &resolution.context) {
+                Err(e) => {
+                    error!("  ✗ Failed to render {}: {}", entry.key, e);
+                    let hint = e.name.as_deref().and_then(|name| match entries.iter().find(|other| other.key == name) {
+                        Some(other) => Some(format!("{} is defined at {} but could not be resolved", name, other.location())),
+                        None => closest_match(name, entries.iter().map(|other| other.key.as_str()))
+                            .map(|key| format!("did you mean {}?", key)),
+                    });
+                    let diagnostic = entry.diagnostic(e.message.clone(), e.span.clone()).with_hint(hint.or(e.hint));
+                    resolution.render_errors.push((entry.key.clone(), diagnostic));
+                }
+                Ok(_) => {
+                    // Shouldn't happen, but just in case
+                    warn!("  ⚠ Variable {} was renderable but not rendered in iterations", entry.key);
+                }
+            }
+        }
+
+        resolution.iterations = iteration;
+        info!("Phase 2 complete: {} total variables in context (rendered in {} iterations)",
+              resolution.context.len(), iteration);
+    }
+
+    /// Applies the `${KEY:=default}` assignments made while rendering `entry`
+    ///
+    /// Only keys defined in the template are assigned, since the whole context ends up in the
+    /// .env file; computed keys that are still waiting to be rendered keep their own expression.
+    fn assign(
+        &self,
+        entry: &EnvEntry,
+        refs: &[ResolvedRef],
+        entries: &[EnvEntry],
+        pending: &[&EnvEntry],
+        resolution: &mut Resolution,
+    ) {
+        for r in refs {
+            let name = &r.var.name;
+            if !matches!(r.var.expansion, Expansion::Assign { .. })
+                || r.source != RefSource::Default
+                || !entries.iter().any(|e| &e.key == name)
+                || pending.iter().any(|e| &e.key == name)
+            {
+                continue;
+            }
+            debug!("  ✓ {} = {} (assigned by {})", name, r.value, entry.key);
+            resolution.context.insert(name.clone(), r.value.clone());
+            resolution.sources.insert(name.clone(), Source::Assigned { line: entry.line, expression: r.var.expression() });
+            resolution.missing_keys.retain(|m| &m.key != name);
+        }
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, raw_value: &str, line: usize) -> EnvEntry {
+        EnvEntry::new(key, raw_value, line)
+    }
+
+    #[test]
+    fn test_render_computed_with_dependencies() {
+        let resolver = Resolver::new();
+        let entries = vec![
+            entry("PG_USER", "", 1),
+            entry("PG_HOST", "${PSENV_TEST_UNSET_CTX_HOST:-localhost}", 2),
+            entry("DATABASE_URL", "postgresql://${PG_USER}@${PG_ADDR}", 3),
+            entry("PG_ADDR", "${PG_HOST}:5432", 4),
+        ];
+
+        let mut resolution = Resolution::default();
+        resolution.context.insert("PG_USER".to_string(), "app".to_string());
+        resolver.render_computed(&entries, &mut resolution);
+
+        assert_eq!(resolution.context["DATABASE_URL"], "postgresql://app@localhost:5432");
+        assert!(resolution.render_errors.is_empty());
+        assert_eq!(resolution.iterations, 2);
+
+        match &resolution.sources["PG_ADDR"] {
+            Source::Computed { line, refs, .. } => {
+                assert_eq!(*line, 4);
+                assert_eq!(refs[0].var.name, "PG_HOST");
+            }
+            other => panic!("unexpected source: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_dependencies_of() {
+        let resolver = Resolver::new();
+        let entries = vec![
+            entry("APP_URL", "http://${APP_HOST}:${APP_PORT}", 1),
+            entry("APP_HOST", "${CTX_HOST:-localhost}", 2),
+            entry("APP_PORT", "3000", 3),
+            entry("UNRELATED", "", 4),
+        ];
+
+        let keys: Vec<String> = resolver.dependencies_of(&entries, "APP_URL").into_iter().map(|e| e.key).collect();
+        assert_eq!(keys, vec!["APP_URL", "APP_HOST", "APP_PORT"]);
+
+        assert!(resolver.dependencies_of(&entries, "MISSING").is_empty());
+    }
+
+    #[test]
+    fn test_enforce_required_and_optional() {
+        let mut entries = vec![
+            entry("API_TOKEN", "", 1),
+            entry("SENTRY_DSN", "", 2),
+            entry("DEBUG", "", 3),
+        ];
+        entries[0].required = Some(true);
+        entries[1].required = Some(false);
+
+        let missing = |keys: &[&str]| {
+            let mut resolution = Resolution::default();
+            for key in keys {
+                resolution.missing_keys.push(MissingKey {
+                    key: key.to_string(),
+                    ssm_path: format!("/studio-dev/{}", key),
+                });
+            }
+            resolution
+        };
+
+        // Required keys always fail, and the error names the path that was tried
+        let err = missing(&["API_TOKEN"]).enforce(&entries, false, MissingPolicy::Omit).unwrap_err();
+        assert!(err.to_string().contains("API_TOKEN (tried /studio-dev/API_TOKEN)"));
+
+        // Unannotated keys follow require_all
+        assert!(missing(&["DEBUG"]).enforce(&entries, true, MissingPolicy::Warn).is_err());
+        assert!(missing(&["DEBUG"]).enforce(&entries, false, MissingPolicy::Warn).is_ok());
+
+        // Optional keys are handled by the policy
+        assert!(missing(&["SENTRY_DSN"]).enforce(&entries, true, MissingPolicy::Error).is_err());
+        let mut resolution = missing(&["SENTRY_DSN"]);
+        resolution.enforce(&entries, true, MissingPolicy::Omit).unwrap();
+        assert!(!resolution.context.contains_key("SENTRY_DSN"));
+
+        let mut resolution = missing(&["SENTRY_DSN"]);
+        resolution.enforce(&entries, true, MissingPolicy::WriteEmpty).unwrap();
+        assert_eq!(resolution.context["SENTRY_DSN"], "");
+    }
+
+    #[test]
+    fn test_render_computed_reports_errors() {
+        let resolver = Resolver::new();
+        let entries = vec![entry("URL", "${PSENV_TEST_UNSET_MISSING}", 1)];
+
+        let mut resolution = Resolution::default();
+        resolver.render_computed(&entries, &mut resolution);
+
+        assert_eq!(resolution.render_errors.len(), 1);
+        assert_eq!(resolution.render_errors[0].0, "URL");
+        assert!(!resolution.context.contains_key("URL"));
+    }
+
+    #[test]
+    fn test_assign_default_sets_template_keys() {
+        let resolver = Resolver::new();
+        let entries = vec![
+            entry("PG_PORT", "", 1),
+            entry("DATABASE_URL", "postgresql://db:${PG_PORT:=5432}/${PSENV_TEST_UNSET_DB:=app}", 2),
+        ];
+
+        let mut resolution = Resolution::default();
+        resolution.missing_keys.push(MissingKey { key: "PG_PORT".to_string(), ssm_path: "/app/PG_PORT".to_string() });
+        resolver.render_computed(&entries, &mut resolution);
+
+        assert_eq!(resolution.context["DATABASE_URL"], "postgresql://db:5432/app");
+        assert_eq!(resolution.context["PG_PORT"], "5432");
+        assert_eq!(resolution.sources["PG_PORT"].describe("PG_PORT"), "assigned by ${PG_PORT:=5432}, template line 2");
+        assert!(resolution.missing_keys.is_empty());
+        // Names outside the template are only assigned within the expression
+        assert!(!resolution.context.contains_key("PSENV_TEST_UNSET_DB"));
+    }
+
+    #[test]
+    fn test_assign_default_replaces_empty_values() {
+        let resolver = Resolver::new();
+        let entries = vec![entry("A", "", 1), entry("B", "${A:=x}-${A}", 2)];
+
+        let mut resolution = Resolution::default();
+        resolution.context.insert("A".to_string(), String::new());
+        resolver.render_computed(&entries, &mut resolution);
+
+        assert_eq!(resolution.context["B"], "x-x");
+        assert_eq!(resolution.context["A"], "x");
+    }
+
+    #[test]
+    fn test_escaped_and_raw_values_are_not_expanded() {
+        let resolver = Resolver::new();
+        let mut raw = entry("N8N_EXPRESSION", "{{ ${NODE_NAME} }}", 2);
+        raw.raw = true;
+        let entries = vec![entry("CADDY_UPSTREAM", "{$${http.request.host}}", 1), raw.clone()];
+
+        let mut resolution = Resolution::default();
+        resolver.render_computed(&entries, &mut resolution);
+
+        // Escape-only and @raw entries are raw keys, resolved from Parameter Store, env or the template
+        assert!(resolution.context.is_empty());
+        assert!(resolution.render_errors.is_empty());
+
+        let (value, _) = resolver.resolve_local(&entries[0]).unwrap();
+        assert_eq!(value, "{${http.request.host}}");
+        let (value, _) = resolver.resolve_local(&raw).unwrap();
+        assert_eq!(value, "{{ ${NODE_NAME} }}");
+    }
+
+    #[test]
+    fn test_render_errors_point_at_the_reference() {
+        let resolver = Resolver::new();
+        let entries = vec![
+            entry("PSENV_TEST_PG_HOST", "", 1),
+            entry("PSENV_TEST_PG_PASSWORD", "", 2),
+            entry("DATABASE_URL", "postgresql://${PSENV_TEST_PG_PASSWORD}@${PSENV_TEST_PG_HOTS}/app", 3),
+        ];
+
+        let mut resolution = Resolution::default();
+        resolution.context.insert("PSENV_TEST_PG_PASSWORD".to_string(), "pw".to_string());
+        resolver.render_computed(&entries, &mut resolution);
+
+        let diagnostic = &resolution.render_errors[0].1;
+        assert_eq!((diagnostic.line, diagnostic.column, diagnostic.width), (3, 53, 21));
+        assert_eq!(diagnostic.hint.as_deref(), Some("did you mean PSENV_TEST_PG_HOST?"));
+
+        // A reference to a key that exists but has no value says so instead
+        resolution.context.remove("PSENV_TEST_PG_PASSWORD");
+        resolution.render_errors.clear();
+        resolver.render_computed(&entries, &mut resolution);
+        assert_eq!(
+            resolution.render_errors[0].1.hint.as_deref(),
+            Some("PSENV_TEST_PG_PASSWORD is defined at line 2 but could not be resolved")
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
+use std::collections::HashMap;
+
+use crate::template_parser::EnvEntry;
+
+/// Expected kind of value, declared with `# @type ...`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueType {
+    String,
+    /// `int`, `int 1..65535`, `int 0..` or `int ..100`
+    Int { min: Option<i64>, max: Option<i64> },
+    /// `true`/`false`, `1`/`0`, `yes`/`no` or `on`/`off`
+    Bool,
+    /// `url` or `url https,http`; the scheme must be one of the listed ones
+    Url { schemes: Vec<String> },
+    Email,
+    /// `enum development,staging,production`
+    Enum(Vec<String>),
+}
+
+impl ValueType {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (name, args) = spec.trim().split_once(char::is_whitespace).unwrap_or((spec.trim(), ""));
+        let args = args.trim();
+
+        let value_type = match name {
+            "string" => ValueType::String,
+            "int" => {
+                let (min, max) = parse_range(args)?;
+                ValueType::Int { min, max }
+            }
+            "bool" => ValueType::Bool,
+            "url" => ValueType::Url { schemes: split_list(args) },
+            "email" => ValueType::Email,
+            "enum" => {
+                let values = split_list(args);
+                if values.is_empty() {
+                    bail!("enum needs a comma-separated list of allowed values");
+                }
+                ValueType::Enum(values)
+            }
+            _ => bail!("unknown type '{}', expected one of string, int, bool, url, email, enum", name),
+        };
+
+        if !args.is_empty() && matches!(value_type, ValueType::String | ValueType::Bool | ValueType::Email) {
+            bail!("type '{}' takes no arguments", name);
+        }
+        Ok(value_type)
+    }
+
+    /// Describes why `value` doesn't fit this type, or `None` if it does
+    fn check(&self, value: &str) -> Option<String> {
+        match self {
+            ValueType::String => None,
+            ValueType::Int { min, max } => match value.parse::<i64>() {
+                Err(_) => Some("must be an integer".to_string()),
+                Ok(n) if min.is_some_and(|min| n < min) || max.is_some_and(|max| n > max) => {
+                    Some(format!("must be in range {}", format_range(*min, *max)))
+                }
+                Ok(_) => None,
+            },
+            ValueType::Bool => {
+                let valid = ["true", "false", "1", "0", "yes", "no", "on", "off"];
+                (!valid.contains(&value.to_lowercase().as_str())).then(|| "must be a boolean".to_string())
+            }
+            ValueType::Url { schemes } => {
+                let url_regex = Regex::new(r"^([a-zA-Z][a-zA-Z0-9+.\-]*)://[^\s/?#]+([/?#]\S*)?$").unwrap();
+                match url_regex.captures(value) {
+                    None => Some("must be a URL".to_string()),
+                    Some(captures) => {
+                        let scheme = captures[1].to_lowercase();
+                        (!schemes.is_empty() && !schemes.contains(&scheme))
+                            .then(|| format!("must be a URL with scheme {}", schemes.join(" or ")))
+                    }
+                }
+            }
+            ValueType::Email => {
+                let email_regex = Regex::new(r"^[^@\s]+@[^@\s]+\.[^@\s]+$").unwrap();
+                (!email_regex.is_match(value)).then(|| "must be an email address".to_string())
+            }
+            ValueType::Enum(values) => {
+                (!values.iter().any(|v| v == value)).then(|| format!("must be one of {}", values.join(", ")))
+            }
+        }
+    }
+}
+
+/// Constraints on the value of a single key
+#[derive(Debug, Clone)]
+pub struct Constraints {
+    pub value_type: ValueType,
+    /// From `# @pattern ...` as declared, and compiled to match the whole value
+    pub pattern: Option<(String, Regex)>,
+    /// From `# @length 8..64`, counted in characters
+    pub min_length: Option<i64>,
+    pub max_length: Option<i64>,
+}
+
+impl Constraints {
+    /// Reads the `@type`, `@pattern` and `@length` annotations; `None` if there are none
+    pub fn from_entry(entry: &EnvEntry) -> Result<Option<Self>> {
+        if entry.value_type.is_none() && entry.pattern.is_none() && entry.length.is_none() {
+            return Ok(None);
+        }
+
+        // Errors point at the annotation that declared the constraint
+        let invalid = |name: &str, e: anyhow::Error| {
+            entry.annotation_diagnostic(name, format!("Invalid constraint for {}: {}", entry.key, e))
+        };
+
+        let value_type = match &entry.value_type {
+            Some(spec) => ValueType::parse(spec).map_err(|e| invalid("type", e))?,
+            None => ValueType::String,
+        };
+        let pattern = entry.pattern.as_deref()
+            .map(|p| {
+                Regex::new(&format!("^(?:{})$", p))
+                    .map(|regex| (p.to_string(), regex))
+                    .map_err(|e| invalid("pattern", anyhow!("invalid @pattern: {}", e)))
+            })
+            .transpose()?;
+        let (min_length, max_length) = match entry.length.as_deref() {
+            Some(range) => parse_range(range).map_err(|e| invalid("length", e))?,
+            None => (None, None),
+        };
+
+        Ok(Some(Constraints { value_type, pattern, min_length, max_length }))
+    }
+
+    /// Every way in which `value` violates the constraints
+    pub fn check(&self, value: &str) -> Vec<String> {
+        let mut problems: Vec<String> = self.value_type.check(value).into_iter().collect();
+
+        if let Some((declared, pattern)) = &self.pattern {
+            if !pattern.is_match(value) {
+                problems.push(format!("must match {}", declared));
+            }
+        }
+
+        let length = value.chars().count() as i64;
+        if self.min_length.is_some_and(|min| length < min) || self.max_length.is_some_and(|max| length > max) {
+            problems.push(format!("length must be in range {}", format_range(self.min_length, self.max_length)));
+        }
+
+        problems
+    }
+}
+
+/// A resolved value that doesn't meet its template constraints
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub key: String,
+    pub message: String,
+}
+
+/// Constraints declared in a template, keyed by variable name
+#[derive(Debug, Default)]
+pub struct Schema {
+    constraints: HashMap<String, Constraints>,
+}
+
+impl Schema {
+    /// Collects the constraints of all entries, failing on the first invalid declaration
+    pub fn from_entries(entries: &[EnvEntry]) -> Result<Self> {
+        let mut constraints = HashMap::new();
+        for entry in entries {
+            if let Some(parsed) = Constraints::from_entry(entry)? {
+                constraints.insert(entry.key.clone(), parsed);
+            }
+        }
+        Ok(Schema { constraints })
+    }
+
+    /// Checks every constrained key that has a value, sorted by key
+    pub fn validate(&self, values: &HashMap<String, String>) -> Vec<Violation> {
+        let mut violations: Vec<Violation> = self.constraints.iter()
+            .filter_map(|(key, constraints)| values.get(key).map(|value| (key, constraints.check(value))))
+            .flat_map(|(key, problems)| {
+                problems.into_iter().map(move |message| Violation { key: key.clone(), message })
+            })
+            .collect();
+        violations.sort_by(|a, b| a.key.cmp(&b.key));
+        violations
+    }
+}
+
+/// Parses `min..max` where either bound may be left out
+fn parse_range(range: &str) -> Result<(Option<i64>, Option<i64>)> {
+    if range.is_empty() {
+        return Ok((None, None));
+    }
+    let (min, max) = range.split_once("..")
+        .ok_or_else(|| anyhow!("invalid range '{}', expected min..max", range))?;
+    let bound = |s: &str| -> Result<Option<i64>> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Ok(None);
+        }
+        s.parse().map(Some).map_err(|_| anyhow!("invalid range '{}', bounds must be integers", range))
+    };
+    Ok((bound(min)?, bound(max)?))
+}
+
+fn format_range(min: Option<i64>, max: Option<i64>) -> String {
+    format!(
+        "{}..{}",
+        min.map(|n| n.to_string()).unwrap_or_default(),
+        max.map(|n| n.to_string()).unwrap_or_default()
+    )
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Diagnostic;
+    use crate::template_parser::TemplateParser;
+
+    fn constrained(key: &str, value_type: Option<&str>, pattern: Option<&str>, length: Option<&str>) -> EnvEntry {
+        let mut entry = EnvEntry::new(key, "", 1);
+        entry.value_type = value_type.map(String::from);
+        entry.pattern = pattern.map(String::from);
+        entry.length = length.map(String::from);
+        entry
+    }
+
+    fn check(value_type: &str, value: &str) -> Vec<String> {
+        let entry = constrained("KEY", Some(value_type), None, None);
+        Constraints::from_entry(&entry).unwrap().unwrap().check(value)
+    }
+
+    #[test]
+    fn test_value_types() {
+        assert!(check("int 1..65535", "5432").is_empty());
+        assert_eq!(check("int 1..65535", "70000"), vec!["must be in range 1..65535"]);
+        assert_eq!(check("int", "54x"), vec!["must be an integer"]);
+        assert!(check("int ..10", "-3").is_empty());
+
+        assert!(check("bool", "Yes").is_empty());
+        assert_eq!(check("bool", "maybe"), vec!["must be a boolean"]);
+
+        assert!(check("url", "redis://:pw@cache:6379/0").is_empty());
+        assert!(check("url postgres, postgresql", "postgresql://app@db/app").is_empty());
+        assert_eq!(check("url https", "http://example.com"), vec!["must be a URL with scheme https"]);
+        assert_eq!(check("url", "example.com"), vec!["must be a URL"]);
+
+        assert!(check("email", "ops@example.com").is_empty());
+        assert_eq!(check("email", "ops@localhost"), vec!["must be an email address"]);
+
+        assert!(check("enum development,production", "production").is_empty());
+        assert_eq!(check("enum development,production", "prod"), vec!["must be one of development, production"]);
+    }
+
+    #[test]
+    fn test_pattern_and_length() {
+        let entry = constrained("SLUG", None, Some("[a-z-]+"), Some("3..8"));
+        let constraints = Constraints::from_entry(&entry).unwrap().unwrap();
+
+        assert!(constraints.check("my-app").is_empty());
+        assert_eq!(constraints.check("my-app-1"), vec!["must match [a-z-]+"]);
+        assert_eq!(constraints.check("my-long-app"), vec!["length must be in range 3..8"]);
+    }
+
+    #[test]
+    fn test_invalid_declarations() {
+        // Errors point at the annotation, not at the value
+        let template = "# @description Port\n# @type integer\nPORT=8080\n# @length 8-64\nPASSWORD=\n";
+        let entries = TemplateParser::new().parse_str(template).unwrap();
+        let err = Schema::from_entries(&entries[1..]).unwrap_err();
+        let diagnostic = err.downcast::<Diagnostic>().unwrap();
+        assert!(diagnostic.message.starts_with("Invalid constraint for PORT: unknown type 'integer'"));
+        assert_eq!((diagnostic.line, diagnostic.column, diagnostic.width), (2, 9, 7));
+        assert_eq!(diagnostic.source_line, "# @type integer");
+
+        let err = Schema::from_entries(&entries[..1]).unwrap_err();
+        let diagnostic = err.downcast::<Diagnostic>().unwrap();
+        assert!(diagnostic.message.starts_with("Invalid constraint for PASSWORD: invalid range '8-64'"));
+        assert_eq!((diagnostic.line, diagnostic.column, diagnostic.width), (4, 11, 4));
+
+        // Without an annotation line, e.g. from a structured template, the value is shown
+        let err = Schema::from_entries(&[constrained("PORT", Some("integer"), None, None)]).unwrap_err();
+        let diagnostic = err.downcast::<Diagnostic>().unwrap();
+        assert_eq!((diagnostic.line, diagnostic.column), (1, 6));
+
+        assert!(Schema::from_entries(&[constrained("PORT", Some("int 1-10"), None, None)]).is_err());
+        assert!(Schema::from_entries(&[constrained("ENV", Some("enum"), None, None)]).is_err());
+        assert!(Schema::from_entries(&[constrained("X", None, Some("("), None)]).is_err());
+    }
+
+    #[test]
+    fn test_validate_skips_missing_values() {
+        let entries = vec![
+            constrained("PORT", Some("int"), None, None),
+            constrained("NODE_ENV", Some("enum development,production"), None, None),
+            EnvEntry::new("UNCONSTRAINED", "", 3),
+        ];
+        let schema = Schema::from_entries(&entries).unwrap();
+
+        let mut values = HashMap::new();
+        values.insert("PORT".to_string(), "http".to_string());
+        values.insert("UNCONSTRAINED".to_string(), "anything".to_string());
+
+        assert_eq!(
+            schema.validate(&values),
+            vec![Violation { key: "PORT".to_string(), message: "must be an integer".to_string() }]
+        );
+    }
+}
@@ -1,9 +1,13 @@
 use regex::Regex;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+
+use crate::template_parser::EnvEntry;
 
 pub struct SecretMasker {
     sensitive_patterns: Vec<Regex>,
     sensitive_keywords: HashSet<String>,
+    /// Declared with `# @secret` / `# @public`, takes precedence over the key name
+    declared: HashMap<String, bool>,
 }
 
 impl SecretMasker {
@@ -39,10 +43,28 @@ impl SecretMasker {
         SecretMasker {
             sensitive_patterns,
             sensitive_keywords,
+            declared: HashMap::new(),
         }
     }
 
+    /// Uses the `@secret` / `@public` annotations of the template entries
+    pub fn with_entries(mut self, entries: &[EnvEntry]) -> Self {
+        self.declared = entries.iter()
+            .filter_map(|e| e.sensitive.map(|sensitive| (e.key.clone(), sensitive)))
+            .collect();
+        self
+    }
+
+    /// Sensitivity declared in the template, if any
+    pub fn declared_sensitivity(&self, key: &str) -> Option<bool> {
+        self.declared.get(key).copied()
+    }
+
     pub fn is_sensitive_key(&self, key: &str) -> bool {
+        if let Some(sensitive) = self.declared_sensitivity(key) {
+            return sensitive;
+        }
+
         let key_lower = key.to_lowercase();
 
         // Check if any keyword appears in the key
@@ -110,6 +132,21 @@ mod tests {
         assert!(!masker.is_sensitive_key("LOG_LEVEL"));
     }
 
+    #[test]
+    fn test_declared_sensitivity() {
+        let mut public = EnvEntry::new("PUBLIC_KEY_ID", "", 1);
+        public.sensitive = Some(false);
+        let mut secret = EnvEntry::new("SLACK_WEBHOOK", "", 2);
+        secret.sensitive = Some(true);
+        let masker = SecretMasker::new().with_entries(&[public, secret]);
+
+        assert!(!masker.is_sensitive_key("PUBLIC_KEY_ID"));
+        assert!(masker.is_sensitive_key("SLACK_WEBHOOK"));
+        // Keys without annotations still go by their name
+        assert!(masker.is_sensitive_key("API_KEY"));
+        assert_eq!(masker.declared_sensitivity("API_KEY"), None);
+    }
+
     #[test]
     fn test_mask_value() {
         let masker = SecretMasker::new();
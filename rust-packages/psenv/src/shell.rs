@@ -0,0 +1,146 @@
+use clap::ValueEnum;
+use log::warn;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum Shell {
+    #[value(name = "bash")]
+    Bash,
+    #[value(name = "zsh")]
+    Zsh,
+    #[value(name = "fish")]
+    Fish,
+    #[value(name = "powershell")]
+    Powershell,
+}
+
+impl Shell {
+    /// A single statement that exports `key` with `value` in this shell
+    pub fn export_line(&self, key: &str, value: &str) -> String {
+        match self {
+            Shell::Bash | Shell::Zsh => format!("export {}={}", key, posix_quote(value)),
+            Shell::Fish => format!("set -gx {} {}", key, fish_quote(value)),
+            Shell::Powershell if is_plain_name(key) => format!("$env:{} = {}", key, powershell_quote(value)),
+            Shell::Powershell => format!("${{env:{}}} = {}", key, powershell_quote(value)),
+        }
+    }
+
+    /// Whether `key` can be set as a variable in this shell
+    ///
+    /// PowerShell sets any name through `${env:...}`; the others only take identifiers.
+    pub fn accepts_name(&self, key: &str) -> bool {
+        match self {
+            Shell::Bash | Shell::Zsh | Shell::Fish => {
+                key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && is_plain_name(key)
+            }
+            Shell::Powershell => true,
+        }
+    }
+
+    /// Export statements for all values, sorted by key
+    ///
+    /// Keys the shell can't set (like `app.port` in bash) are skipped with a warning.
+    pub fn export_script(&self, values: &HashMap<String, String>) -> String {
+        let mut sorted_keys: Vec<&String> = values.keys().collect();
+        sorted_keys.sort();
+
+        sorted_keys
+            .into_iter()
+            .filter(|key| {
+                let accepted = self.accepts_name(key);
+                if !accepted {
+                    let shell = self.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default();
+                    warn!("Skipping {}: not a valid variable name in {}", key, shell);
+                }
+                accepted
+            })
+            .map(|key| self.export_line(key, &values[key]) + "\n")
+            .collect()
+    }
+}
+
+/// Quotes a value for POSIX shells (sh, bash, zsh)
+///
+/// Everything inside single quotes is literal, so only `'` itself needs escaping.
+pub fn posix_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Quotes a value for fish, where `\` and `'` are the only escapes inside single quotes
+pub fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+/// Quotes a value for PowerShell, where a single quote is escaped by doubling it
+pub fn powershell_quote(value: &str) -> String {
+    // PowerShell also treats typographic quotes as single quotes
+    let escaped: String = value
+        .chars()
+        .flat_map(|c| match c {
+            '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' => vec![c, c],
+            _ => vec![c],
+        })
+        .collect();
+    format!("'{}'", escaped)
+}
+
+fn is_plain_name(key: &str) -> bool {
+    key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_posix_quote() {
+        assert_eq!(posix_quote("plain"), "'plain'");
+        assert_eq!(posix_quote(""), "''");
+        assert_eq!(posix_quote("it's"), r"'it'\''s'");
+        assert_eq!(posix_quote("$HOME \"x\"\nline"), "'$HOME \"x\"\nline'");
+    }
+
+    #[test]
+    fn test_fish_quote() {
+        assert_eq!(fish_quote("it's"), r"'it\'s'");
+        assert_eq!(fish_quote(r"back\slash"), r"'back\\slash'");
+        assert_eq!(fish_quote("$HOME\nline"), "'$HOME\nline'");
+    }
+
+    #[test]
+    fn test_powershell_quote() {
+        assert_eq!(powershell_quote("it's"), "'it''s'");
+        assert_eq!(powershell_quote("$env:HOME `n"), "'$env:HOME `n'");
+        assert_eq!(powershell_quote("it\u{2019}s"), "'it\u{2019}\u{2019}s'");
+    }
+
+    #[test]
+    fn test_export_line() {
+        assert_eq!(Shell::Bash.export_line("KEY", "a'b"), r"export KEY='a'\''b'");
+        assert_eq!(Shell::Zsh.export_line("KEY", "v"), "export KEY='v'");
+        assert_eq!(Shell::Fish.export_line("KEY", "v"), "set -gx KEY 'v'");
+        assert_eq!(Shell::Powershell.export_line("KEY", "v"), "$env:KEY = 'v'");
+        assert_eq!(Shell::Powershell.export_line("app.name", "v"), "${env:app.name} = 'v'");
+    }
+
+    #[test]
+    fn test_export_script_is_sorted() {
+        let mut values = HashMap::new();
+        values.insert("B".to_string(), "2".to_string());
+        values.insert("A".to_string(), "1".to_string());
+
+        assert_eq!(Shell::Bash.export_script(&values), "export A='1'\nexport B='2'\n");
+    }
+
+    #[test]
+    fn test_export_script_skips_invalid_names() {
+        let mut values = HashMap::new();
+        values.insert("app.port".to_string(), "8080".to_string());
+        values.insert("1ST".to_string(), "x".to_string());
+        values.insert("_PORT".to_string(), "8080".to_string());
+
+        assert_eq!(Shell::Bash.export_script(&values), "export _PORT='8080'\n");
+        assert_eq!(Shell::Fish.export_script(&values), "set -gx _PORT '8080'\n");
+        assert_eq!(Shell::Powershell.export_script(&values).lines().count(), 3);
+    }
+}
@@ -0,0 +1,307 @@
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use serde::de::{MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+
+use crate::template_parser::EnvEntry;
+
+/// Syntax of a template file
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum TemplateFormat {
+    /// By file extension: `.yaml`/`.yml`, `.json` and `.toml` are structured, anything else is .env syntax
+    #[default]
+    #[value(name = "auto")]
+    Auto,
+    /// `.env.example` syntax with `# @annotation` comments
+    #[value(name = "env")]
+    Env,
+    #[value(name = "yaml")]
+    Yaml,
+    #[value(name = "json")]
+    Json,
+    #[value(name = "toml")]
+    Toml,
+}
+
+impl TemplateFormat {
+    /// Resolves `Auto` from the extension of `path`
+    pub fn for_path(self, path: &str) -> Self {
+        if self != TemplateFormat::Auto {
+            return self;
+        }
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => TemplateFormat::Yaml,
+            Some("json") => TemplateFormat::Json,
+            Some("toml") => TemplateFormat::Toml,
+            _ => TemplateFormat::Env,
+        }
+    }
+}
+
+/// Top level of a structured template
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StructuredTemplate {
+    keys: KeyList,
+}
+
+/// The `keys` table in document order, keeping repeated keys so they can be reported
+struct KeyList(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for KeyList {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyListVisitor;
+
+        impl<'de> Visitor<'de> for KeyListVisitor {
+            type Value = KeyList;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of keys")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<KeyList, A::Error> {
+                let mut keys = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    keys.push(entry);
+                }
+                Ok(KeyList(keys))
+            }
+        }
+
+        deserializer.deserialize_map(KeyListVisitor)
+    }
+}
+
+/// Definition of one key; the same settings as the .env annotations of the same name
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct KeySpec {
+    default: Option<Value>,
+    required: Option<bool>,
+    description: Option<String>,
+    #[serde(rename = "type")]
+    value_type: Option<String>,
+    secret: Option<bool>,
+    example: Option<Value>,
+    pattern: Option<String>,
+    length: Option<String>,
+    source: Option<String>,
+    raw: Option<bool>,
+}
+
+/// Reads a YAML, JSON or TOML template into entries, in document order
+///
+/// A key defined twice yields two entries; the caller applies the duplicates policy.
+///
+/// Keys map either to their default value directly or to a table of settings:
+///
+/// ```yaml
+/// keys:
+///   PG_HOST: localhost
+///   PG_PASSWORD:
+///     required: true
+///     secret: true
+///     source: /shared/postgres/PASSWORD
+/// ```
+pub fn parse_structured(content: &str, format: TemplateFormat, file: Option<&str>) -> Result<Vec<EnvEntry>> {
+    // Deserialized straight from the text, as going through a `Value` map would merge repeated keys
+    let template: StructuredTemplate = match format {
+        TemplateFormat::Yaml => serde_yaml::from_str(content).context("Invalid YAML template")?,
+        TemplateFormat::Json => serde_json::from_str(content).context("Invalid JSON template")?,
+        TemplateFormat::Toml => toml::from_str(content).context("Invalid TOML template")?,
+        TemplateFormat::Auto | TemplateFormat::Env => bail!("{:?} is not a structured template format", format),
+    };
+
+    let lines: Vec<&str> = content.lines().collect();
+    let keys: Vec<&str> = template.keys.0.iter().map(|(key, _)| key.as_str()).collect();
+
+    template.keys.0.iter().enumerate()
+        .map(|(idx, (key, definition))| {
+            let spec = match definition {
+                Value::Object(_) => KeySpec::deserialize(definition)
+                    .with_context(|| format!("Invalid definition of {}", key))?,
+                scalar => KeySpec { default: Some(scalar.clone()), ..KeySpec::default() },
+            };
+            let default = spec.default.as_ref().map(|v| scalar_to_string(key, "default", v)).transpose()?;
+            let raw_value = default.unwrap_or_default();
+
+            let occurrence = keys[..idx].iter().filter(|other| *other == key).count();
+            let (line, column) = locate(&lines, &keys, key, occurrence, &raw_value);
+            let mut entry = EnvEntry::new(key, &raw_value, line + 1);
+            entry.column = column;
+            entry.source_text = lines.get(line).map(|l| l.to_string()).unwrap_or_default();
+            entry.file = file.map(String::from);
+            entry.required = spec.required;
+            entry.description = spec.description;
+            entry.sensitive = spec.secret;
+            entry.value_type = spec.value_type;
+            entry.example = spec.example.as_ref().map(|v| scalar_to_string(key, "example", v)).transpose()?;
+            entry.pattern = spec.pattern;
+            entry.length = spec.length;
+            entry.source = spec.source;
+            entry.raw = spec.raw.unwrap_or(false);
+            Ok(entry)
+        })
+        .collect()
+}
+
+/// Strings are taken as-is, numbers and booleans as written, null as empty
+fn scalar_to_string(key: &str, field: &str, value: &Value) -> Result<String> {
+    match value {
+        Value::Null => Ok(String::new()),
+        Value::String(s) => Ok(s.clone()),
+        Value::Bool(_) | Value::Number(_) => Ok(value.to_string()),
+        Value::Array(_) | Value::Object(_) => {
+            Err(anyhow!("The {} of {} must be a string, number or boolean", field, key))
+        }
+    }
+}
+
+/// 0-based line and 1-based value column of the `occurrence`th (0-based) definition of a key,
+/// for error messages
+///
+/// Uses the line the default value is on when it can be found before the next key,
+/// otherwise the line of the key itself.
+fn locate(lines: &[&str], keys: &[&str], key: &str, occurrence: usize, value: &str) -> (usize, usize) {
+    let Some((key_line, _)) = lines.iter().enumerate().filter(|(_, line)| defines(line, key).is_some()).nth(occurrence) else {
+        return (0, 1);
+    };
+    let next_key = lines.iter().enumerate().skip(key_line + 1)
+        .find(|(_, line)| keys.iter().any(|other| defines(line, other).is_some()))
+        .map_or(lines.len(), |(idx, _)| idx);
+    let after_key = defines(lines[key_line], key).unwrap_or(0) + key.len();
+
+    if !value.is_empty() {
+        for (idx, line) in lines.iter().enumerate().take(next_key).skip(key_line) {
+            let search_from = if idx == key_line { after_key } else { 0 };
+            if let Some(offset) = line[search_from..].find(value) {
+                return (idx, line[..search_from + offset].chars().count() + 1);
+            }
+        }
+    }
+    (key_line, lines[key_line][..after_key].chars().count() + 1)
+}
+
+/// Byte offset of `key` if `line` starts its definition: `KEY:`, `"KEY":`, `KEY =` or `[keys.KEY]`
+fn defines(line: &str, key: &str) -> Option<usize> {
+    let trimmed = line.trim_start();
+    let unprefixed = trimmed.strip_prefix("[keys.").unwrap_or(trimmed);
+    let unquoted = unprefixed.strip_prefix(['"', '\'']).unwrap_or(unprefixed);
+    let rest = unquoted.strip_prefix(key)?;
+    if rest.starts_with(|c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-')) {
+        return None;
+    }
+    Some(line.len() - unquoted.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str, format: TemplateFormat) -> Vec<EnvEntry> {
+        let mut entries = parse_structured(content, format, Some("template")).unwrap();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        entries
+    }
+
+    #[test]
+    fn test_formats_load_the_same_entries() {
+        let yaml = r#"
+keys:
+  PG_HOST: localhost
+  PG_PORT: 5432
+  PG_PASSWORD:
+    required: true
+    secret: true
+    description: Postgres password
+    source: /shared/postgres/PASSWORD
+  DATABASE_URL:
+    type: url postgresql
+    default: postgresql://app:${PG_PASSWORD}@${PG_HOST}:${PG_PORT}/app
+"#;
+        let json = r#"{
+  "keys": {
+    "PG_HOST": "localhost",
+    "PG_PORT": 5432,
+    "PG_PASSWORD": {
+      "required": true,
+      "secret": true,
+      "description": "Postgres password",
+      "source": "/shared/postgres/PASSWORD"
+    },
+    "DATABASE_URL": {
+      "type": "url postgresql",
+      "default": "postgresql://app:${PG_PASSWORD}@${PG_HOST}:${PG_PORT}/app"
+    }
+  }
+}"#;
+        let toml = r#"
+[keys]
+PG_HOST = "localhost"
+PG_PORT = 5432
+
+[keys.PG_PASSWORD]
+required = true
+secret = true
+description = "Postgres password"
+source = "/shared/postgres/PASSWORD"
+
+[keys.DATABASE_URL]
+type = "url postgresql"
+default = "postgresql://app:${PG_PASSWORD}@${PG_HOST}:${PG_PORT}/app"
+"#;
+
+        for (content, format) in [(yaml, TemplateFormat::Yaml), (json, TemplateFormat::Json), (toml, TemplateFormat::Toml)] {
+            let entries = parse(content, format);
+            let keys: Vec<&str> = entries.iter().map(|e| e.key.as_str()).collect();
+            assert_eq!(keys, vec!["DATABASE_URL", "PG_HOST", "PG_PASSWORD", "PG_PORT"], "{:?}", format);
+
+            assert_eq!(entries[0].raw_value, "postgresql://app:${PG_PASSWORD}@${PG_HOST}:${PG_PORT}/app");
+            assert_eq!(entries[0].value_type.as_deref(), Some("url postgresql"));
+            assert_eq!(entries[1].raw_value, "localhost");
+            assert_eq!(entries[2].raw_value, "");
+            assert_eq!(entries[2].required, Some(true));
+            assert_eq!(entries[2].sensitive, Some(true));
+            assert_eq!(entries[2].description.as_deref(), Some("Postgres password"));
+            assert_eq!(entries[2].source.as_deref(), Some("/shared/postgres/PASSWORD"));
+            assert_eq!(entries[3].raw_value, "5432");
+        }
+    }
+
+    #[test]
+    fn test_locations_point_at_the_default() {
+        let yaml = "keys:\n  PG_HOST: localhost\n  DATABASE_URL:\n    description: URL\n    default: postgresql://${PG_HOTS}/app\n";
+        let entries = parse(yaml, TemplateFormat::Yaml);
+
+        assert_eq!((entries[1].line, entries[1].column), (2, 12));
+        assert_eq!((entries[0].line, entries[0].column), (5, 14));
+
+        let diagnostic = entries[0].diagnostic("unknown", 13..23);
+        assert_eq!((diagnostic.line, diagnostic.column, diagnostic.width), (5, 27, 10));
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        let err = parse_structured("keys:\n  PG_HOST:\n    requried: true\n", TemplateFormat::Yaml, None).unwrap_err();
+        assert!(format!("{:#}", err).contains("Invalid definition of PG_HOST: unknown field `requried`"));
+
+        let err = parse_structured("{\"PG_HOST\": \"x\"}", TemplateFormat::Json, None).unwrap_err();
+        assert!(format!("{:#}", err).contains("unknown field `PG_HOST`"));
+
+        let err = parse_structured("[keys]\nHOSTS = [\"a\"]\n", TemplateFormat::Toml, None).unwrap_err();
+        assert_eq!(err.to_string(), "The default of HOSTS must be a string, number or boolean");
+
+        assert!(parse_structured("keys: [", TemplateFormat::Yaml, None).is_err());
+    }
+
+    #[test]
+    fn test_format_for_path() {
+        assert_eq!(TemplateFormat::Auto.for_path("env.yml"), TemplateFormat::Yaml);
+        assert_eq!(TemplateFormat::Auto.for_path("config/env.toml"), TemplateFormat::Toml);
+        assert_eq!(TemplateFormat::Auto.for_path(".env.example"), TemplateFormat::Env);
+        assert_eq!(TemplateFormat::Json.for_path(".env.example"), TemplateFormat::Json);
+    }
+}
@@ -1,69 +1,747 @@
 use anyhow::{Context, Result};
-use log::debug;
+use clap::ValueEnum;
+use log::{debug, warn};
 use regex::Regex;
 use std::collections::HashMap;
 use std::fs;
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::condition::Condition;
+use crate::diagnostic::Diagnostic;
+use crate::structured_template::{self, TemplateFormat};
+use crate::template_renderer::TemplateRenderer;
 
 #[derive(Debug, Clone)]
 pub struct EnvEntry {
     pub key: String,
     pub raw_value: String,
+    /// 1-based line number in the template file
+    pub line: usize,
+    /// 1-based column where the value starts, after any opening quote
+    pub column: usize,
+    /// Text of the lines the entry was read from, for error messages; several for multi-line values
+    pub source_text: String,
+    /// File the entry was read from, which differs from the template for `# @include`d entries
+    pub file: Option<String>,
+    /// Set by `# @required` / `# @optional`; `None` follows `--require-all`
+    pub required: Option<bool>,
+    /// Declared on a commented-out line (`# KEY=value`)
+    pub commented: bool,
+    /// From `# @description ...`; several lines are joined with spaces
+    pub description: Option<String>,
+    /// `Some(true)` for `# @secret`, `Some(false)` for `# @public`; `None` leaves it to the key name
+    pub sensitive: Option<bool>,
+    /// From `# @type ...`, e.g. `url` or `int`
+    pub value_type: Option<String>,
+    /// From `# @example ...`
+    pub example: Option<String>,
+    /// From `# @pattern ...`, a regex the whole value must match
+    pub pattern: Option<String>,
+    /// From `# @length min..max`
+    pub length: Option<String>,
+    /// From `# @source name`: Parameter Store name to read instead of the key, absolute or relative to the prefix
+    pub source: Option<String>,
+    /// Set by `# @raw`: the value is used literally, without `${...}` expansion
+    pub raw: bool,
+    /// Where each `# @annotation` was declared, by name, so errors can point at it
+    pub annotation_sites: HashMap<String, AnnotationSite>,
+}
+
+/// A `# @name args` line, with the span of its arguments
+#[derive(Debug, Clone)]
+pub struct AnnotationSite {
+    /// 1-based line number
+    pub line: usize,
+    pub source_text: String,
+    pub span: Range<usize>,
+}
+
+impl EnvEntry {
+    pub fn new(key: &str, raw_value: &str, line: usize) -> Self {
+        EnvEntry {
+            key: key.to_string(),
+            raw_value: raw_value.to_string(),
+            line,
+            column: key.chars().count() + 2,
+            source_text: format!("{}={}", key, raw_value),
+            file: None,
+            required: None,
+            commented: false,
+            description: None,
+            sensitive: None,
+            value_type: None,
+            example: None,
+            pattern: None,
+            length: None,
+            source: None,
+            raw: false,
+            annotation_sites: HashMap::new(),
+        }
+    }
+
+    /// Where the entry is defined, e.g. `.env.example:12`
+    pub fn location(&self) -> String {
+        match &self.file {
+            Some(file) => format!("{}:{}", file, self.line),
+            None => format!("line {}", self.line),
+        }
+    }
+
+    /// Parameter Store path the value is read from under `prefix`
+    pub fn parameter_path(&self, prefix: &str) -> String {
+        match self.source.as_deref() {
+            Some(absolute) if absolute.starts_with('/') => absolute.to_string(),
+            Some(relative) => format!("{}{}", prefix, relative),
+            None => format!("{}{}", prefix, self.key),
+        }
+    }
+
+    /// The value as a template to render, or `None` for a `# @raw` entry
+    pub fn template(&self) -> Option<&str> {
+        (!self.raw).then_some(self.raw_value.as_str())
+    }
+
+    /// Error about the part of the value at byte range `span`, shown against the source line it is on
+    pub fn diagnostic(&self, message: impl Into<String>, span: Range<usize>) -> Diagnostic {
+        // Quoting and escapes may shift the value against the source, so look the text up
+        let value_start = self.source_text.char_indices().nth(self.column - 1).map_or(self.source_text.len(), |(i, _)| i);
+        let snippet = self.raw_value.get(span.clone()).unwrap_or_default();
+        let start = match self.source_text[value_start..].find(snippet) {
+            Some(offset) if !snippet.is_empty() => value_start + offset,
+            _ => value_start,
+        };
+
+        // In a multi-line value, point at the line the snippet starts on
+        let line_start = self.source_text[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line = self.line + self.source_text[..start].matches('\n').count();
+        let source_line = self.source_text[line_start..].lines().next().unwrap_or_default();
+        let start = start - line_start;
+        Diagnostic::new(message, line, source_line, start..start + snippet.len())
+            .with_file(self.file.clone())
+    }
+
+    /// Error at the arguments of `# @name`, or at the value when the setting didn't come
+    /// from an annotation (e.g. in a structured template)
+    pub fn annotation_diagnostic(&self, name: &str, message: impl Into<String>) -> Diagnostic {
+        match self.annotation_sites.get(name) {
+            Some(site) => Diagnostic::new(message, site.line, &site.source_text, site.span.clone())
+                .with_file(self.file.clone()),
+            None => self.diagnostic(message, 0..self.raw_value.len()),
+        }
+    }
+
+    fn apply_annotation(&mut self, name: &str, args: &str, site: AnnotationSite) {
+        let line_num = site.line;
+        self.annotation_sites.insert(name.to_string(), site);
+        let value = || (!args.is_empty()).then(|| args.to_string());
+        match name {
+            "required" => self.required = Some(true),
+            "optional" => self.required = Some(false),
+            "secret" => self.sensitive = Some(true),
+            "public" => self.sensitive = Some(false),
+            "description" => {
+                self.description = match (self.description.take(), value()) {
+                    (Some(previous), Some(more)) => Some(format!("{} {}", previous, more)),
+                    (previous, more) => more.or(previous),
+                }
+            }
+            "type" => self.value_type = value(),
+            "example" => self.example = value(),
+            "pattern" => self.pattern = value(),
+            "length" => self.length = value(),
+            "source" => self.source = value(),
+            "raw" => self.raw = true,
+            _ => debug!("Ignoring unknown annotation '@{}' on line {}", name, line_num),
+        }
+    }
+}
+
+/// Which key names are recognized in templates and .env files
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum KeyGrammar {
+    /// Uppercase letters, digits and underscores (`DB_HOST`)
+    #[default]
+    #[value(name = "upper")]
+    Upper,
+    /// Any POSIX name: letters of either case, digits and underscores (`node_env`)
+    #[value(name = "posix")]
+    Posix,
+    /// POSIX names that may also contain dots and dashes (`spring.datasource.url`)
+    #[value(name = "dotted")]
+    Dotted,
+}
+
+impl KeyGrammar {
+    fn key_pattern(&self) -> &'static str {
+        match self {
+            KeyGrammar::Upper => r"[A-Z_][A-Z0-9_]*",
+            KeyGrammar::Posix => r"[A-Za-z_][A-Za-z0-9_]*",
+            KeyGrammar::Dotted => r"[A-Za-z_][A-Za-z0-9_.\-]*",
+        }
+    }
+
+    /// Regex matching an entry line with leading whitespace removed
+    ///
+    /// Matches lines like: KEY=value, KEY= (empty value), # KEY=value (commented), export KEY=value
+    /// Capture groups: 1=comment marker (optional), 2=KEY, 3=everything after '='
+    pub fn entry_regex(&self) -> Regex {
+        Regex::new(&format!(r"^(#)?\s*(?:export\s+)?({})\s*=(.*)$", self.key_pattern())).unwrap()
+    }
+
+    /// Regex matching a whole key name
+    pub fn key_regex(&self) -> Regex {
+        Regex::new(&format!("^{}$", self.key_pattern())).unwrap()
+    }
+}
+
+/// What to do when an active key is defined more than once
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum DuplicatePolicy {
+    /// Fail, listing every duplicate with its line numbers
+    #[default]
+    #[value(name = "error")]
+    Error,
+    /// Use the first definition and warn
+    #[value(name = "keep-first")]
+    KeepFirst,
+    /// Use the last definition and warn
+    #[value(name = "keep-last")]
+    KeepLast,
+}
+
+/// What to do with commented-out entries (`# KEY=value`)
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum CommentedPolicy {
+    /// Leave them out entirely
+    #[value(name = "ignore")]
+    Ignore,
+    /// Resolve them, but don't fail when they are missing (unless annotated `@required`)
+    #[value(name = "optional")]
+    Optional,
+    /// Treat them like active entries
+    #[default]
+    #[value(name = "active")]
+    Active,
 }
 
+/// An active key defined again, as (key, first line, error at the redefinition)
+type Duplicate = (String, usize, Diagnostic);
+
 pub struct TemplateParser {
     env_key_regex: Regex,
+    key_regex: Regex,
+    duplicates: DuplicatePolicy,
+    commented: CommentedPolicy,
+    format: TemplateFormat,
 }
 
 impl TemplateParser {
     pub fn new() -> Self {
-        // Regex to match environment variable keys in .env files
-        // Matches lines like: KEY=value, KEY= (empty value), # KEY=value (commented)
-        let env_key_regex = Regex::new(r"^#?\s*([A-Z_][A-Z0-9_]*)\s*=(.*)$").unwrap();
+        TemplateParser {
+            env_key_regex: KeyGrammar::default().entry_regex(),
+            key_regex: KeyGrammar::default().key_regex(),
+            duplicates: DuplicatePolicy::default(),
+            commented: CommentedPolicy::default(),
+            format: TemplateFormat::default(),
+        }
+    }
+
+    pub fn with_key_grammar(mut self, key_grammar: KeyGrammar) -> Self {
+        self.env_key_regex = key_grammar.entry_regex();
+        self.key_regex = key_grammar.key_regex();
+        self
+    }
+
+    /// Syntax of the template; included files are always detected by extension
+    pub fn with_format(mut self, format: TemplateFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_duplicates(mut self, duplicates: DuplicatePolicy) -> Self {
+        self.duplicates = duplicates;
+        self
+    }
 
-        TemplateParser { env_key_regex }
+    pub fn with_commented(mut self, commented: CommentedPolicy) -> Self {
+        self.commented = commented;
+        self
     }
 
     pub fn parse_template(&self, template_path: &str) -> Result<Vec<EnvEntry>> {
         debug!("Parsing template file: {}", template_path);
 
-        let content = fs::read_to_string(template_path)
-            .with_context(|| format!("Failed to read template file: {}", template_path))?;
+        let format = self.format.for_path(template_path);
+        let entries = self.parse_file(Path::new(template_path), format, &mut Vec::new())?;
+        Ok(self.finish(entries))
+    }
+
+    /// Parses a template held in memory; `# @include` paths are relative to the working directory
+    ///
+    /// The format must be set with `with_format` unless the template uses .env syntax.
+    pub fn parse_str(&self, content: &str) -> Result<Vec<EnvEntry>> {
+        debug!("Parsing template from string");
+
+        let entries = match self.format {
+            TemplateFormat::Auto | TemplateFormat::Env => {
+                self.parse_content(content, None, Path::new(""), &mut Vec::new())?
+            }
+            format => self.parse_structured(content, format, None)?,
+        };
+        Ok(self.finish(entries))
+    }
+
+    /// Reads a template from `reader` (e.g. stdin) and parses it like `parse_str`
+    pub fn parse_reader(&self, mut reader: impl Read) -> Result<Vec<EnvEntry>> {
+        let mut content = String::new();
+        reader.read_to_string(&mut content).context("Failed to read template")?;
+        self.parse_str(&content)
+    }
+
+    /// Applies the commented policy and sorts the entries by key
+    fn finish(&self, entries: HashMap<String, EnvEntry>) -> Vec<EnvEntry> {
+        let mut result: Vec<EnvEntry> = entries.into_values()
+            .filter(|entry| !(entry.commented && self.commented == CommentedPolicy::Ignore))
+            .map(|mut entry| {
+                if entry.commented && self.commented == CommentedPolicy::Optional {
+                    entry.required.get_or_insert(false);
+                }
+                entry
+            })
+            .collect();
+        result.sort_by(|a, b| a.key.cmp(&b.key));
+
+        debug!("Parsed {} unique entries from template", result.len());
+
+        result
+    }
+
+    /// Parses one file and the files it includes
+    ///
+    /// `stack` holds the canonical paths of the files currently being parsed, to detect cycles.
+    fn parse_file(
+        &self,
+        path: &Path,
+        format: TemplateFormat,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<HashMap<String, EnvEntry>> {
+        let file = path.display().to_string();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read template file: {}", file))?;
 
-        let mut entries = HashMap::new();
+        if format != TemplateFormat::Env {
+            return self.parse_structured(&content, format, Some(&file));
+        }
+        stack.push(canonical_path(path));
+
+        // Relative includes are relative to the including file
+        let entries = self.parse_content(&content, Some(&file), path.parent().unwrap_or(Path::new("")), stack)?;
+        stack.pop();
+        Ok(entries)
+    }
 
-        for (line_num, line) in content.lines().enumerate() {
+    /// Parses a YAML, JSON or TOML template, checking its key names against the key grammar
+    fn parse_structured(
+        &self,
+        content: &str,
+        format: TemplateFormat,
+        file: Option<&str>,
+    ) -> Result<HashMap<String, EnvEntry>> {
+        let entries = structured_template::parse_structured(content, format, file)?;
+        // Points at the key before the value, or anywhere on the line the value is on
+        let error_at = |entry: &EnvEntry, message: String| {
+            let value_start = entry.source_text.char_indices().nth(entry.column - 1).map_or(entry.source_text.len(), |(idx, _)| idx);
+            let start = entry.source_text[..value_start].rfind(&entry.key)
+                .or_else(|| entry.source_text.find(&entry.key))
+                .unwrap_or(0);
+            Diagnostic::new(message, entry.line, &entry.source_text, start..start + entry.key.len())
+                .with_file(entry.file.clone())
+        };
+        if let Some(entry) = entries.iter().find(|e| !self.key_regex.is_match(&e.key)) {
+            return Err(error_at(entry, format!("Invalid key name {}", entry.key))
+                .with_hint(Some("see --key-grammar for the allowed names".to_string()))
+                .into());
+        }
+
+        let mut result: HashMap<String, EnvEntry> = HashMap::new();
+        let mut duplicates: Vec<Duplicate> = Vec::new();
+        for entry in entries {
+            if let Some(existing) = result.get(&entry.key) {
+                let error = error_at(&entry, format!("Duplicate key {}", entry.key));
+                duplicates.push((entry.key.clone(), existing.line, error));
+                if self.duplicates != DuplicatePolicy::KeepLast {
+                    continue;
+                }
+            }
+            result.insert(entry.key.clone(), entry);
+        }
+        self.check_duplicates(duplicates, file)?;
+        Ok(result)
+    }
+
+    /// Applies the duplicates policy to the active keys that were defined more than once
+    fn check_duplicates(&self, mut duplicates: Vec<Duplicate>, file: Option<&str>) -> Result<()> {
+        if duplicates.is_empty() {
+            return Ok(());
+        }
+        let lines: Vec<String> = duplicates.iter()
+            .map(|(key, first, again)| format!("  {}: line {} and line {}", key, first, again.line))
+            .collect();
+        let file = file.unwrap_or("template");
+        match self.duplicates {
+            DuplicatePolicy::Error => {
+                // The first redefinition is shown, any others are listed in the hint
+                let (key, first, error) = duplicates.remove(0);
+                let mut hint = format!("{} is first defined on line {}", key, first);
+                if !duplicates.is_empty() {
+                    let others: Vec<String> = duplicates.iter()
+                        .map(|(key, first, again)| format!("{} (lines {} and {})", key, first, again.line))
+                        .collect();
+                    hint.push_str(&format!("; also duplicated: {}", others.join(", ")));
+                }
+                hint.push_str("; use --duplicates keep-first or keep-last to allow");
+                return Err(error.with_hint(Some(hint)).into());
+            }
+            DuplicatePolicy::KeepFirst => {
+                warn!("Duplicate keys in {}, keeping the first definition:\n{}", file, lines.join("\n"))
+            }
+            DuplicatePolicy::KeepLast => {
+                warn!("Duplicate keys in {}, keeping the last definition:\n{}", file, lines.join("\n"))
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses template text; `file` labels entries and errors, `dir` is where includes are looked up
+    fn parse_content(
+        &self,
+        content: &str,
+        file: Option<&str>,
+        dir: &Path,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<HashMap<String, EnvEntry>> {
+        let at = |line: usize| match file {
+            Some(file) => format!("{}:{}", file, line),
+            None => format!("line {}", line),
+        };
+
+        let mut entries: HashMap<String, EnvEntry> = HashMap::new();
+        // Entries from `# @include` files, overridden by the entries of this file
+        let mut included: HashMap<String, EnvEntry> = HashMap::new();
+        // Active keys defined more than once
+        let mut duplicates: Vec<Duplicate> = Vec::new();
+        // Annotations from `# @name args` comment lines, applied to the next entry
+        let mut pending_annotations: Vec<(String, String, AnnotationSite)> = Vec::new();
+        // Open `# @if` blocks, innermost last
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut lines = content.lines().enumerate();
+
+        while let Some((line_num, line)) = lines.next() {
             let trimmed = line.trim();
+            let active = blocks.last().is_none_or(Block::is_active);
+            let indent = line.len() - line.trim_start().len();
+            let error_at = |span: Range<usize>, message: String| {
+                Diagnostic::new(message, line_num + 1, line, span).with_file(file.map(String::from))
+            };
+
+            if let Some(directive) = trimmed.strip_prefix('#').map(str::trim).and_then(|c| c.strip_prefix('@')) {
+                let (name, args) = directive.split_once(char::is_whitespace).unwrap_or((directive, ""));
+                match name {
+                    "if" => {
+                        let condition = Condition::parse(args).map_err(|e| {
+                            let start = line.rfind(args).filter(|_| !args.is_empty()).unwrap_or(indent);
+                            error_at(start..start + args.len().max(1), format!("Invalid @if condition: {}", e))
+                        })?;
+                        // Conditions see the shell env first, then literal values defined above
+                        let renderer = TemplateRenderer::new();
+                        let lookup = |name: &str| {
+                            std::env::var(name).ok().or_else(|| {
+                                entries.get(name).or_else(|| included.get(name))
+                                    .filter(|e| !e.commented && !e.template().is_some_and(|t| renderer.contains_variables(t)))
+                                    .map(|e| e.template().map_or_else(|| e.raw_value.clone(), |t| renderer.unescape(t)))
+                            })
+                        };
+                        blocks.push(Block {
+                            line: line_num + 1,
+                            parent_active: active,
+                            condition: active && condition.evaluate(&lookup),
+                            in_else: false,
+                        });
+                        continue;
+                    }
+                    "else" => {
+                        let directive = indent..indent + trimmed.len();
+                        match blocks.last_mut() {
+                            Some(block) if !block.in_else => block.in_else = true,
+                            Some(block) => {
+                                let hint = format!("the @if on line {} already has an @else", block.line);
+                                return Err(error_at(directive, "Second @else for the same @if".to_string())
+                                    .with_hint(Some(hint))
+                                    .into());
+                            }
+                            None => return Err(error_at(directive, "@else without @if".to_string()).into()),
+                        }
+                        continue;
+                    }
+                    "endif" => {
+                        if blocks.pop().is_none() {
+                            return Err(error_at(indent..indent + trimmed.len(), "@endif without @if".to_string()).into());
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            // Entries, includes and annotations in inactive blocks are skipped entirely
+            if !active {
+                continue;
+            }
+
+            // A blank line ends the annotation block
+            if trimmed.is_empty() {
+                pending_annotations.clear();
+                continue;
+            }
+
+            if let Some(annotation) = trimmed.strip_prefix('#').map(str::trim).and_then(|c| c.strip_prefix('@')) {
+                let (name, args) = annotation.split_once(char::is_whitespace).unwrap_or((annotation, ""));
+                if name == "include" {
+                    let path = dir.join(args.trim());
+                    let canonical = canonical_path(&path);
+                    if let Some(start) = stack.iter().position(|p| p == &canonical) {
+                        let cycle: Vec<String> = stack[start..].iter().chain([&canonical])
+                            .map(|p| p.display().to_string())
+                            .collect();
+                        let span = line.rfind(args.trim()).unwrap_or(indent);
+                        return Err(error_at(span..span + args.trim().len(), format!("Include cycle: {}", cycle.join(" -> "))).into());
+                    }
+                    let from_include = self.parse_file(&path, TemplateFormat::Auto.for_path(args.trim()), stack)
+                        .with_context(|| format!("Failed to include {} from {}", args.trim(), at(line_num + 1)))?;
+                    for (key, entry) in from_include {
+                        if let Some(previous) = included.get(&key) {
+                            warn!(
+                                "{} is included from both {} and {}, using the latter",
+                                key, previous.location(), entry.location()
+                            );
+                        }
+                        included.insert(key, entry);
+                    }
+                } else {
+                    let args = args.trim();
+                    // Without arguments the whole annotation is highlighted
+                    let span = match line.rfind(args).filter(|_| !args.is_empty()) {
+                        Some(start) => start..start + args.len(),
+                        None => indent..indent + trimmed.len(),
+                    };
+                    let site = AnnotationSite { line: line_num + 1, source_text: line.to_string(), span };
+                    pending_annotations.push((name.to_string(), args.to_string(), site));
+                }
+                continue;
+            }
 
-            // Skip empty lines and comments that don't contain env vars
-            if trimmed.is_empty() || (trimmed.starts_with('#') && !trimmed.contains('=')) {
+            // Skip comments that don't contain env vars
+            if trimmed.starts_with('#') && !trimmed.contains('=') {
                 continue;
             }
 
-            if let Some(captures) = self.env_key_regex.captures(trimmed) {
-                if let Some(key_match) = captures.get(1) {
-                    let key = key_match.as_str().to_string();
-                    let raw_value = captures.get(2)
-                        .map(|m| m.as_str().trim().to_string())
-                        .unwrap_or_default();
-
-                    debug!("Found key '{}' = '{}' on line {}", key, raw_value, line_num + 1);
-                    entries.insert(key.clone(), EnvEntry {
-                        key,
-                        raw_value,
-                    });
+            // Leading whitespace only: trailing spaces may be part of a quoted value
+            if let Some(captures) = self.env_key_regex.captures(line.trim_start()) {
+                let commented = captures.get(1).is_some();
+                let key = captures[2].to_string();
+                let key_span = indent + captures.get(2).map_or(0, |m| m.start())..indent + captures.get(2).map_or(0, |m| m.end());
+                let rest = captures.get(3).map(|m| m.as_str()).unwrap_or_default();
+                // Byte offset of the value in the line, past whitespace and any opening quote
+                let mut value_start = indent + captures.get(3).map_or(0, |m| m.start()) + rest.len() - rest.trim_start().len();
+                let quoted = rest.trim_start().starts_with(['"', '\'', '`']);
+
+                // Lines after the first that a quoted value spans
+                let mut continuation: Vec<&str> = Vec::new();
+                let raw_value = if commented {
+                    // Commented entries never continue onto the next line
+                    parse_value(rest, || None).unwrap_or_else(|| rest.trim().to_string())
+                } else {
+                    let next_line = || lines.next().map(|(_, l)| l).inspect(|l| continuation.push(l));
+                    parse_value(rest, next_line).ok_or_else(|| {
+                        error_at(value_start..line.len(), format!("Unterminated quoted value for {}", key))
+                            .with_hint(Some("add the closing quote".to_string()))
+                    })?
+                };
+                if quoted {
+                    value_start += 1;
+                }
+
+                debug!("Found key '{}' = '{}' on line {}", key, raw_value, line_num + 1);
+                let mut entry = EnvEntry::new(&key, &raw_value, line_num + 1);
+                entry.file = file.map(String::from);
+                entry.column = line[..value_start].chars().count() + 1;
+                entry.source_text = [line].into_iter().chain(continuation).collect::<Vec<_>>().join("\n");
+                entry.commented = commented;
+                for (name, args, site) in pending_annotations.drain(..) {
+                    entry.apply_annotation(&name, &args, site);
+                }
+
+                let Some(existing) = entries.get(&key) else {
+                    entries.insert(key, entry);
+                    continue;
+                };
+                // A commented line next to an active one is an alternative, not a redefinition
+                let replace = match (existing.commented, commented) {
+                    (false, true) | (true, true) => {
+                        debug!("Ignoring commented {} on line {} (already defined on line {})", key, entry.line, existing.line);
+                        false
+                    }
+                    (true, false) => true,
+                    (false, false) => {
+                        let error = error_at(key_span, format!("Duplicate key {}", key));
+                        duplicates.push((key.clone(), existing.line, error));
+                        self.duplicates == DuplicatePolicy::KeepLast
+                    }
+                };
+                if replace {
+                    entries.insert(key, entry);
                 }
             }
         }
 
-        let mut result: Vec<EnvEntry> = entries.into_values().collect();
-        result.sort_by(|a, b| a.key.cmp(&b.key));
+        if let Some(block) = blocks.last() {
+            let line = content.lines().nth(block.line - 1).unwrap_or_default();
+            let indent = line.len() - line.trim_start().len();
+            return Err(Diagnostic::new("@if is never closed with @endif", block.line, line, indent..line.trim_end().len())
+                .with_file(file.map(String::from))
+                .into());
+        }
 
-        debug!("Parsed {} unique entries from template", result.len());
+        self.check_duplicates(duplicates, file)?;
 
-        Ok(result)
+        // Local entries override included ones, unless they are only commented-out alternatives
+        for (key, entry) in entries {
+            match included.get(&key) {
+                Some(base) if entry.commented && !base.commented => {
+                    debug!("Ignoring commented {} at {} (included from {})", key, entry.location(), base.location());
+                }
+                Some(base) => {
+                    debug!("{} at {} overrides {}", key, entry.location(), base.location());
+                    included.insert(key, entry);
+                }
+                None => {
+                    included.insert(key, entry);
+                }
+            }
+        }
+
+        Ok(included)
+    }
+}
+
+/// The path with symlinks and `..` resolved, or as given if it doesn't exist
+fn canonical_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// A `# @if` block that is being parsed
+struct Block {
+    line: usize,
+    /// Whether the lines around the block are active
+    parent_active: bool,
+    condition: bool,
+    /// Past the `# @else` line
+    in_else: bool,
+}
+
+impl Block {
+    fn is_active(&self) -> bool {
+        self.parent_active && self.condition != self.in_else
     }
 }
 
+/// Parses the value part of an entry (everything after `=`), following dotenv rules
+///
+/// - `'single'` and `` `backtick` `` quoted values are taken literally
+/// - `"double"` quoted values expand `\n`, `\r`, `\t`, `\"` and `\\`; other escapes are kept as-is
+/// - Quoted values may span several lines; `next_line` supplies the following lines
+/// - Unquoted values end at a `#` preceded by whitespace and are trimmed
+///
+/// Node's `dotenv` only expands `\n` and `\r` and ends unquoted values at any `#`; the
+/// extra escapes and bare `#` are kept for templates, and output files avoid relying on them.
+///
+/// Returns `None` if a quote is never closed.
+pub(crate) fn parse_value<'a>(rest: &str, mut next_line: impl FnMut() -> Option<&'a str>) -> Option<String> {
+    let value = rest.trim_start();
+    let Some(quote) = value.chars().next().filter(|c| matches!(c, '"' | '\'' | '`')) else {
+        return Some(strip_inline_comment(rest).trim().to_string());
+    };
+
+    let mut raw = String::new();
+    let mut segment = value[1..].to_string();
+    loop {
+        if let Some(end) = closing_quote(&segment, quote) {
+            raw.push_str(&segment[..end]);
+            break;
+        }
+        raw.push_str(&segment);
+        raw.push('\n');
+        segment = next_line()?.to_string();
+    }
+
+    if quote == '"' {
+        Some(unescape(&raw))
+    } else {
+        Some(raw)
+    }
+}
+
+/// Byte offset of the quote that closes a value, skipping escaped quotes inside double quotes
+fn closing_quote(segment: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (idx, c) in segment.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote == '"' => escaped = true,
+            _ if c == quote => return Some(idx),
+            _ => {}
+        }
+    }
+    None
+}
+
+pub(crate) fn strip_inline_comment(value: &str) -> &str {
+    let mut previous = None;
+    for (idx, c) in value.char_indices() {
+        if c == '#' && previous.is_some_and(char::is_whitespace) {
+            return &value[..idx];
+        }
+        previous = Some(c);
+    }
+    value
+}
+
+fn unescape(raw: &str) -> String {
+    let mut result = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some('"') => result.push('"'),
+            Some('\\') => result.push('\\'),
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
 impl Default for TemplateParser {
     fn default() -> Self {
         Self::new()
@@ -121,6 +799,10 @@ ANOTHER_KEY=value
 
         let api_key = entries.iter().find(|e| e.key == "API_KEY").unwrap();
         assert_eq!(api_key.raw_value, "");
+
+        // Check line numbers are recorded
+        assert_eq!(db_host.line, 3);
+        assert_eq!(api_key.line, 8);
     }
 
     #[test]
@@ -142,6 +824,437 @@ DB_HOST=localhost
         let expected_keys = vec!["API_KEY", "DB_HOST", "DB_PORT"];
 
         assert_eq!(keys, expected_keys);
+        let commented: Vec<bool> = entries.iter().map(|e| e.commented).collect();
+        assert_eq!(commented, vec![true, false, true]);
+    }
+
+    #[test]
+    fn test_parse_template_commented_policy() {
+        let template_content = r#"
+DB_HOST=localhost
+# DB_PORT=5432
+
+# @required
+# API_KEY=
+"#;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), template_content).unwrap();
+        let parse = |policy: CommentedPolicy| {
+            TemplateParser::new()
+                .with_commented(policy)
+                .parse_template(temp_file.path().to_str().unwrap())
+                .unwrap()
+        };
+
+        let ignored = parse(CommentedPolicy::Ignore);
+        assert_eq!(ignored.len(), 1);
+        assert_eq!(ignored[0].key, "DB_HOST");
+
+        let optional = parse(CommentedPolicy::Optional);
+        let required = |key: &str| optional.iter().find(|e| e.key == key).unwrap().required;
+        assert_eq!(required("DB_HOST"), None);
+        assert_eq!(required("DB_PORT"), Some(false));
+        // An explicit annotation still wins
+        assert_eq!(required("API_KEY"), Some(true));
+
+        assert_eq!(parse(CommentedPolicy::Active).iter().filter(|e| e.required.is_some()).count(), 1);
+    }
+
+    #[test]
+    fn test_parse_template_with_required_annotations() {
+        let parser = TemplateParser::new();
+
+        let template_content = r#"
+# @optional
+SENTRY_DSN=
+
+# Postgres password for the app user
+# @required
+PG_PASSWORD=
+PG_HOST=localhost
+
+# @optional
+
+DEBUG=false
+"#;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), template_content).unwrap();
+
+        let entries = parser.parse_template(temp_file.path().to_str().unwrap()).unwrap();
+        let required = |key: &str| entries.iter().find(|e| e.key == key).unwrap().required;
+
+        assert_eq!(required("SENTRY_DSN"), Some(false));
+        assert_eq!(required("PG_PASSWORD"), Some(true));
+        // Annotations only apply to the entry right below them
+        assert_eq!(required("PG_HOST"), None);
+        // A blank line ends the annotation block
+        assert_eq!(required("DEBUG"), None);
+    }
+
+    #[test]
+    fn test_parse_template_duplicates() {
+        let template_content = r#"
+DB_HOST=localhost
+# DB_HOST=db.internal
+DB_PORT=5432
+API_URL=http://first
+# API_URL=http://commented
+API_URL=http://second
+# CACHE_TTL=60
+CACHE_TTL=120
+"#;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), template_content).unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let value = |entries: &[EnvEntry], key: &str| entries.iter().find(|e| e.key == key).unwrap().raw_value.clone();
+
+        let err = TemplateParser::new().parse_template(path).unwrap_err();
+        let diagnostic = err.downcast::<Diagnostic>().unwrap();
+        assert_eq!(diagnostic.message, "Duplicate key API_URL");
+        assert_eq!((diagnostic.line, diagnostic.column, diagnostic.width), (7, 1, 7));
+        assert_eq!(diagnostic.file.as_deref(), Some(path));
+        let hint = diagnostic.hint.unwrap();
+        assert!(hint.starts_with("API_URL is first defined on line 5;"));
+        assert!(!hint.contains("DB_HOST"));
+        assert!(!hint.contains("CACHE_TTL"));
+
+        let first = TemplateParser::new().with_duplicates(DuplicatePolicy::KeepFirst).parse_template(path).unwrap();
+        assert_eq!(value(&first, "API_URL"), "http://first");
+        assert_eq!(value(&first, "DB_HOST"), "localhost");
+        // The active line wins over a commented one, wherever it is
+        assert_eq!(value(&first, "CACHE_TTL"), "120");
+
+        let last = TemplateParser::new().with_duplicates(DuplicatePolicy::KeepLast).parse_template(path).unwrap();
+        assert_eq!(value(&last, "API_URL"), "http://second");
+        assert_eq!(value(&last, "DB_HOST"), "localhost");
+    }
+
+    #[test]
+    fn test_parse_template_key_grammar() {
+        let template_content = r#"
+DB_HOST=localhost
+node_env=production
+Umami_Secret=
+spring.datasource.url=jdbc:postgresql://db/app
+log-level=debug
+"#;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), template_content).unwrap();
+        let keys = |grammar: KeyGrammar| -> Vec<String> {
+            TemplateParser::new().with_key_grammar(grammar)
+                .parse_template(temp_file.path().to_str().unwrap())
+                .unwrap()
+                .into_iter()
+                .map(|e| e.key)
+                .collect()
+        };
+
+        assert_eq!(keys(KeyGrammar::Upper), vec!["DB_HOST"]);
+        assert_eq!(keys(KeyGrammar::Posix), vec!["DB_HOST", "Umami_Secret", "node_env"]);
+        assert_eq!(
+            keys(KeyGrammar::Dotted),
+            vec!["DB_HOST", "Umami_Secret", "log-level", "node_env", "spring.datasource.url"]
+        );
+    }
+
+    #[test]
+    fn test_parse_template_dotenv_syntax() {
+        let parser = TemplateParser::new();
+
+        let template_content = r#"
+export NODE_ENV=production
+SINGLE='it is ${NOT_EXPANDED} \n'
+DOUBLE="line1\nline2 \"quoted\" \$HOME"
+BACKTICK=`it's "both"`
+INLINE=value # trailing comment
+COLOR=#fff
+QUOTED_HASH="a # b" # comment
+PADDED="  spaced  "
+PRIVATE_KEY="-----BEGIN KEY-----
+abc
+-----END KEY-----"
+AFTER=1
+"#;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), template_content).unwrap();
+
+        let entries = parser.parse_template(temp_file.path().to_str().unwrap()).unwrap();
+        let entry = |key: &str| entries.iter().find(|e| e.key == key).unwrap();
+
+        assert_eq!(entry("NODE_ENV").raw_value, "production");
+        assert_eq!(entry("SINGLE").raw_value, r"it is ${NOT_EXPANDED} \n");
+        assert_eq!(entry("DOUBLE").raw_value, "line1\nline2 \"quoted\" \\$HOME");
+        assert_eq!(entry("BACKTICK").raw_value, r#"it's "both""#);
+        assert_eq!(entry("INLINE").raw_value, "value");
+        assert_eq!(entry("COLOR").raw_value, "#fff");
+        assert_eq!(entry("QUOTED_HASH").raw_value, "a # b");
+        assert_eq!(entry("PADDED").raw_value, "  spaced  ");
+        assert_eq!(entry("PRIVATE_KEY").raw_value, "-----BEGIN KEY-----\nabc\n-----END KEY-----");
+        assert_eq!(entry("PRIVATE_KEY").line, 10);
+        assert_eq!(entry("AFTER").line, 13);
+    }
+
+    #[test]
+    fn test_parse_template_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("infra/postgres")).unwrap();
+        fs::create_dir_all(dir.path().join("apps/web")).unwrap();
+        fs::write(
+            dir.path().join("infra/postgres/.env.shared"),
+            "COMMON_POSTGRES_PASSWORD=\nPG_HOST=postgres\nPG_PORT=5432\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("apps/web/.env.example"),
+            "# @include ../../infra/postgres/.env.shared\nPG_HOST=localhost\nAPP_NAME=web\n",
+        )
+        .unwrap();
+
+        let template = dir.path().join("apps/web/.env.example");
+        let entries = TemplateParser::new().parse_template(template.to_str().unwrap()).unwrap();
+        let entry = |key: &str| entries.iter().find(|e| e.key == key).unwrap();
+
+        assert_eq!(entries.len(), 4);
+        // Local keys override included ones
+        assert_eq!(entry("PG_HOST").raw_value, "localhost");
+        assert_eq!(entry("PG_HOST").line, 2);
+        assert_eq!(entry("PG_PORT").line, 3);
+        assert!(entry("PG_PORT").file.as_ref().unwrap().ends_with(".env.shared"));
+        assert!(entry("PG_PORT").location().ends_with(".env.shared:3"));
+    }
+
+    #[test]
+    fn test_parse_str_and_reader() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = dir.path().join(".env.shared");
+        fs::write(&shared, "PG_PORT=5432\n").unwrap();
+
+        let template = format!("# @include {}\nPG_HOST=localhost\nDATABASE_URL=postgresql://${{PG_HOST}}\n", shared.display());
+        let entries = TemplateParser::new().parse_str(&template).unwrap();
+        let keys: Vec<&str> = entries.iter().map(|e| e.key.as_str()).collect();
+
+        assert_eq!(keys, vec!["DATABASE_URL", "PG_HOST", "PG_PORT"]);
+        assert_eq!(entries[1].file, None);
+        assert_eq!(entries[1].location(), "line 2");
+        assert_eq!(entries[2].file.as_deref(), Some(shared.to_str().unwrap()));
+
+        let from_reader = TemplateParser::new().parse_reader(template.as_bytes()).unwrap();
+        assert_eq!(from_reader.len(), 3);
+
+        let err = TemplateParser::new().parse_str("A=1\nB=1\nA=2\n  B=2\n").unwrap_err();
+        let diagnostic = err.downcast::<Diagnostic>().unwrap();
+        assert_eq!((diagnostic.line, diagnostic.column), (3, 1));
+        assert_eq!(
+            diagnostic.hint.as_deref(),
+            Some("A is first defined on line 1; also duplicated: B (lines 2 and 4); use --duplicates keep-first or keep-last to allow")
+        );
+    }
+
+    #[test]
+    fn test_parse_template_include_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.env"), "A=1\n# @include b.env\n").unwrap();
+        fs::write(dir.path().join("b.env"), "# @include a.env\nB=2\n").unwrap();
+
+        let err = TemplateParser::new()
+            .parse_template(dir.path().join("a.env").to_str().unwrap())
+            .unwrap_err();
+        let diagnostic = err.downcast_ref::<Diagnostic>().unwrap();
+        assert!(diagnostic.message.starts_with("Include cycle: "));
+        assert!(diagnostic.message.contains("a.env -> "));
+        assert!(diagnostic.message.contains("b.env -> "));
+        // Reported at the include that closes the cycle
+        assert_eq!(diagnostic.file, Some(dir.path().join("b.env").display().to_string()));
+        assert_eq!((diagnostic.line, diagnostic.column, diagnostic.width), (1, 12, 5));
+    }
+
+    #[test]
+    fn test_parse_template_conditionals() {
+        let template_content = r#"
+DEPLOY_ENV=prod
+# @if DEPLOY_ENV == "prod"
+ANALYTICS_ID=
+SMTP_HOST=smtp.example.com
+# @if PSENV_TEST_UNSET_REGION == "eu"
+EU_ONLY=1
+# @endif
+# @else
+SMTP_HOST=mailpit
+# @endif
+# @if !PSENV_TEST_UNSET_FLAG
+ALWAYS=1
+# @endif
+"#;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), template_content).unwrap();
+
+        let entries = TemplateParser::new().parse_template(temp_file.path().to_str().unwrap()).unwrap();
+        let keys: Vec<&str> = entries.iter().map(|e| e.key.as_str()).collect();
+
+        // The same key in both branches is not a duplicate
+        assert_eq!(keys, vec!["ALWAYS", "ANALYTICS_ID", "DEPLOY_ENV", "SMTP_HOST"]);
+        assert_eq!(entries[3].raw_value, "smtp.example.com");
+    }
+
+    #[test]
+    fn test_parse_template_conditional_errors() {
+        let parse = |content: &str| {
+            let temp_file = NamedTempFile::new().unwrap();
+            fs::write(temp_file.path(), content).unwrap();
+            let err = TemplateParser::new().parse_template(temp_file.path().to_str().unwrap()).unwrap_err();
+            let diagnostic = err.downcast::<Diagnostic>().unwrap();
+            (diagnostic.message, diagnostic.line, diagnostic.column, diagnostic.width)
+        };
+
+        assert_eq!(parse("# @if A\nB=1\n"), ("@if is never closed with @endif".to_string(), 1, 1, 7));
+        assert_eq!(parse("B=1\n  # @endif\n"), ("@endif without @if".to_string(), 2, 3, 8));
+        assert_eq!(parse("# @else\n").0, "@else without @if");
+
+        let (message, line, column, width) = parse("# @if A = 1\n# @endif\n");
+        assert!(message.starts_with("Invalid @if condition: "));
+        assert_eq!((line, column, width), (1, 7, 5));
+    }
+
+    #[test]
+    fn test_parse_template_unterminated_quote() {
+        let parser = TemplateParser::new();
+
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), "A=1\nCERT=\"-----BEGIN\nabc\n").unwrap();
+
+        let err = parser.parse_template(temp_file.path().to_str().unwrap()).unwrap_err();
+        let diagnostic = err.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!(diagnostic.message, "Unterminated quoted value for CERT");
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 6));
+        assert!(err.to_string().contains("2 | CERT=\"-----BEGIN\n  |      ^^^^^^^^^^^"));
+    }
+
+    #[test]
+    fn test_entry_columns_and_diagnostic() {
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), "  export URL = \"http://${HOTS}\"  # web\nPLAIN=${A}\n").unwrap();
+
+        let entries = TemplateParser::new().parse_template(temp_file.path().to_str().unwrap()).unwrap();
+        assert_eq!((entries[1].key.as_str(), entries[1].column), ("URL", 17));
+        assert_eq!(entries[0].column, 7);
+
+        let diagnostic = entries[1].diagnostic("unknown", 7..14);
+        assert_eq!((diagnostic.line, diagnostic.column, diagnostic.width), (1, 24, 7));
+        assert_eq!(diagnostic.source_line, "  export URL = \"http://${HOTS}\"  # web");
+    }
+
+    #[test]
+    fn test_multi_line_diagnostic_points_at_the_right_line() {
+        let entries = TemplateParser::new().parse_str("A=1\nCONFIG=\"first\n  host=${HOTS}\n\"\n").unwrap();
+        let config = entries.iter().find(|e| e.key == "CONFIG").unwrap();
+        assert_eq!(config.source_text, "CONFIG=\"first\n  host=${HOTS}\n\"");
+
+        let start = config.raw_value.find("${HOTS}").unwrap();
+        let diagnostic = config.diagnostic("unknown", start..start + 7);
+        assert_eq!((diagnostic.line, diagnostic.column, diagnostic.width), (3, 8, 7));
+        assert_eq!(diagnostic.source_line, "  host=${HOTS}");
+    }
+
+    #[test]
+    fn test_parse_template_metadata_annotations() {
+        let parser = TemplateParser::new();
+
+        let template_content = r#"
+# @description Postgres password
+# @description for the app user
+# @public
+# @source /shared/postgres/PASSWORD
+PG_PASSWORD=
+
+# @secret
+# @type url
+# @example https://hooks.example.com/T000/B000
+SLACK_WEBHOOK=
+# @type
+PORT=3000
+# @raw
+N8N_EXPRESSION={{ ${NODE_NAME} }}
+"#;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), template_content).unwrap();
+
+        let entries = parser.parse_template(temp_file.path().to_str().unwrap()).unwrap();
+        let entry = |key: &str| entries.iter().find(|e| e.key == key).unwrap();
+
+        assert_eq!(entry("PG_PASSWORD").description.as_deref(), Some("Postgres password for the app user"));
+        assert_eq!(entry("PG_PASSWORD").sensitive, Some(false));
+        assert_eq!(entry("SLACK_WEBHOOK").sensitive, Some(true));
+        assert_eq!(entry("SLACK_WEBHOOK").value_type.as_deref(), Some("url"));
+        assert_eq!(entry("SLACK_WEBHOOK").example.as_deref(), Some("https://hooks.example.com/T000/B000"));
+        assert_eq!(entry("SLACK_WEBHOOK").description, None);
+        assert_eq!(entry("PORT").value_type, None);
+        assert_eq!(entry("N8N_EXPRESSION").template(), None);
+        assert_eq!(entry("PORT").template(), Some("3000"));
+
+        assert_eq!(entry("PG_PASSWORD").parameter_path("/app/"), "/shared/postgres/PASSWORD");
+        assert_eq!(entry("PORT").parameter_path("/app/"), "/app/PORT");
+    }
+
+    #[test]
+    fn test_parse_structured_templates() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = dir.path().join("env.yaml");
+        fs::write(&yaml, "keys:\n  node_env: development\n  PORT:\n    type: int\n    default: 3000\n").unwrap();
+        fs::write(dir.path().join(".env.example"), "# @include env.yaml\nPORT=8080\n").unwrap();
+
+        let posix = TemplateParser::new().with_key_grammar(KeyGrammar::Posix);
+        let entries = posix.parse_template(yaml.to_str().unwrap()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].key, "PORT");
+        assert_eq!(entries[0].raw_value, "3000");
+        assert_eq!(entries[0].value_type.as_deref(), Some("int"));
+        assert_eq!(entries[0].location(), format!("{}:5", yaml.display()));
+
+        // Structured files can be included, and local keys still override them
+        let template = dir.path().join(".env.example");
+        let entries = posix.parse_template(template.to_str().unwrap()).unwrap();
+        assert_eq!(entries[0].raw_value, "8080");
+        assert_eq!(entries[0].value_type, None);
+        assert_eq!(entries[1].key, "node_env");
+
+        let err = TemplateParser::new()
+            .parse_template(yaml.to_str().unwrap())
+            .unwrap_err();
+        let diagnostic = err.downcast::<Diagnostic>().unwrap();
+        assert_eq!(diagnostic.message, "Invalid key name node_env");
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 3));
+
+        let json = TemplateParser::new().with_format(TemplateFormat::Json)
+            .parse_str(r#"{"keys": {"A": "1"}}"#)
+            .unwrap();
+        assert_eq!(json[0].raw_value, "1");
+    }
+
+    #[test]
+    fn test_structured_duplicates_follow_the_policy() {
+        let content = "{\n  \"keys\": {\n    \"API_URL\": \"http://first\",\n    \"API_URL\": \"http://second\"\n  }\n}\n";
+        let json = |duplicates| TemplateParser::new().with_format(TemplateFormat::Json).with_duplicates(duplicates);
+
+        let err = json(DuplicatePolicy::Error).parse_str(content).unwrap_err();
+        let diagnostic = err.downcast::<Diagnostic>().unwrap();
+        assert_eq!(diagnostic.message, "Duplicate key API_URL");
+        assert_eq!((diagnostic.line, diagnostic.column, diagnostic.width), (4, 6, 7));
+        assert!(diagnostic.hint.unwrap().starts_with("API_URL is first defined on line 3;"));
+
+        let first = json(DuplicatePolicy::KeepFirst).parse_str(content).unwrap();
+        assert_eq!(first[0].raw_value, "http://first");
+        assert_eq!(first[0].line, 3);
+
+        let last = json(DuplicatePolicy::KeepLast).parse_str(content).unwrap();
+        assert_eq!(last[0].raw_value, "http://second");
+        assert_eq!(last[0].line, 4);
     }
 
     #[test]
@@ -1,22 +1,270 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use log::debug;
-use regex::Regex;
 use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::diagnostic::closest_match;
+use crate::filter::Filter;
+
+/// Expands `${...}` expressions in template values
+///
+/// Expressions are parsed by recursive descent, so defaults and alternatives can contain
+/// further expressions: `${PG_HOST:-${CTX_PG_HOST:-localhost}}`. Filters follow the name and
+/// transform the result: `${PG_PASSWORD|urlencode}`. A literal `${` is written as `$${` or `\${`.
+pub struct TemplateRenderer;
+
+/// Operator of a `${VAR<op>word}` expansion, as in POSIX shells
+///
+/// The `:` forms treat a variable that is set to an empty string like an unset one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expansion {
+    /// `${VAR}`
+    Plain,
+    /// `${VAR-word}` / `${VAR:-word}`: word if VAR is unset
+    Default { colon: bool },
+    /// `${VAR=word}` / `${VAR:=word}`: like `Default`, and VAR is set to word from then on
+    Assign { colon: bool },
+    /// `${VAR?message}` / `${VAR:?message}`: fails with message if VAR is unset
+    Error { colon: bool },
+    /// `${VAR+word}` / `${VAR:+word}`: word if VAR is set, empty otherwise
+    Alternative { colon: bool },
+}
+
+impl Expansion {
+    fn parse(operator: &str) -> Self {
+        let colon = operator.starts_with(':');
+        match operator.trim_start_matches(':') {
+            "-" => Expansion::Default { colon },
+            "=" => Expansion::Assign { colon },
+            "?" => Expansion::Error { colon },
+            "+" => Expansion::Alternative { colon },
+            _ => Expansion::Plain,
+        }
+    }
+
+    fn operator(&self) -> String {
+        let (colon, op) = match self {
+            Expansion::Plain => return String::new(),
+            Expansion::Default { colon } => (colon, '-'),
+            Expansion::Assign { colon } => (colon, '='),
+            Expansion::Error { colon } => (colon, '?'),
+            Expansion::Alternative { colon } => (colon, '+'),
+        };
+        format!("{}{}", if *colon { ":" } else { "" }, op)
+    }
+
+    fn colon(&self) -> bool {
+        match self {
+            Expansion::Plain => false,
+            Expansion::Default { colon }
+            | Expansion::Assign { colon }
+            | Expansion::Error { colon }
+            | Expansion::Alternative { colon } => *colon,
+        }
+    }
+}
+
+/// A `${VAR}`, `${VAR:-default}` or other expansion found in a template string
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarRef {
+    pub name: String,
+    /// `|filter`s applied to the result, in order
+    pub filters: Vec<Filter>,
+    pub expansion: Expansion,
+    /// Text after the operator: default, alternative or error message
+    pub word: String,
+}
+
+impl VarRef {
+    /// The fallback value of a `-` or `=` expansion
+    pub fn default(&self) -> Option<&str> {
+        matches!(self.expansion, Expansion::Default { .. } | Expansion::Assign { .. }).then_some(self.word.as_str())
+    }
+
+    /// The expansion as written, e.g. `${PG_HOST:-localhost}`
+    pub fn expression(&self) -> String {
+        let filters: String = self.filters.iter().map(|filter| format!("|{}", filter.name())).collect();
+        format!("${{{}{}{}{}}}", self.name, filters, self.expansion.operator(), self.word)
+    }
+}
+
+/// Where a reference got its value from during rendering
+#[derive(Debug, Clone, PartialEq)]
+pub enum RefSource {
+    Context,
+    Environment,
+    /// The default of a `-` or `=` expansion, or the empty result of an unused `+` expansion
+    Default,
+    /// The word of a `+` expansion whose variable is set
+    Alternative,
+}
+
+/// A reference together with the value it was resolved to
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedRef {
+    pub var: VarRef,
+    pub source: RefSource,
+    /// Result of the expansion, before any filters
+    pub value: String,
+}
+
+/// A failure to render a template string, with the byte range of the offending expression
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{message}")]
+pub struct RenderError {
+    pub message: String,
+    pub span: Range<usize>,
+    /// Variable the error is about, if any
+    pub name: Option<String>,
+    pub hint: Option<String>,
+}
+
+/// A piece of a parsed template string
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    /// A `${...}` expression; `word` is the parsed text after the operator
+    Expr { var: VarRef, word: Vec<Segment>, span: Range<usize> },
+}
+
+/// Parses `${...}` expressions by recursive descent over the template string
+struct Parser<'a> {
+    template: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.template[self.pos..]
+    }
+
+    /// Segments up to the end of the input or, in a word, up to the `}` that closes the expression
+    ///
+    /// Like bash, braces inside a word must balance: `${X:-{a}}` renders `{a}`.
+    fn segments(&mut self, in_word: bool) -> Result<Vec<Segment>, RenderError> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut depth = 0;
+
+        while let Some(c) = self.rest().chars().next() {
+            // `$${` and `\${` stand for a literal `${`, whose brace then counts like any other
+            if self.rest().starts_with("$${") || self.rest().starts_with("\\${") {
+                text.push_str("${");
+                self.pos += 3;
+                if in_word {
+                    depth += 1;
+                }
+                continue;
+            }
+            if self.rest().starts_with("${") {
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(self.expression()?);
+                continue;
+            }
+            match c {
+                '{' if in_word => depth += 1,
+                '}' if in_word && depth == 0 => break,
+                '}' if in_word => depth -= 1,
+                _ => {}
+            }
+            text.push(c);
+            self.pos += c.len_utf8();
+        }
+
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(segments)
+    }
+
+    /// One `${NAME}` or `${NAME<op>word}` expression, starting at `${`
+    fn expression(&mut self) -> Result<Segment, RenderError> {
+        let start = self.pos;
+        self.pos += 2;
+
+        let name_len = self.rest().find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(self.rest().len());
+        let name = &self.rest()[..name_len];
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(RenderError {
+                message: "Invalid variable name in ${...} expression".to_string(),
+                span: start..(self.pos + name_len + 1).min(self.template.len()),
+                name: None,
+                hint: Some("names start with a letter or _ and contain only letters, digits and _".to_string()),
+            });
+        }
+        self.pos += name_len;
+
+        let mut filters = Vec::new();
+        while self.rest().starts_with('|') {
+            let filter_start = self.pos;
+            self.pos += 1;
+            let filter_len = self.rest().find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(self.rest().len());
+            let filter_name = &self.rest()[..filter_len];
+            self.pos += filter_len;
+            let filter = Filter::parse(filter_name).ok_or_else(|| RenderError {
+                message: match filter_name {
+                    "" => format!("Missing filter name after ${{{}|", name),
+                    _ => format!("Unknown filter '{}'", filter_name),
+                },
+                span: filter_start..self.pos,
+                name: None,
+                hint: Some(match closest_match(filter_name, Filter::NAMES) {
+                    Some(known) => format!("did you mean {}?", known),
+                    None => format!("available filters are {}", Filter::NAMES.join(", ")),
+                }),
+            })?;
+            filters.push(filter);
+        }
+
+        let operator_len = match self.rest().as_bytes() {
+            [b':', b'-' | b'=' | b'?' | b'+', ..] => 2,
+            [b'-' | b'=' | b'?' | b'+', ..] => 1,
+            _ => 0,
+        };
+        let expansion = Expansion::parse(&self.rest()[..operator_len]);
+        self.pos += operator_len;
+
+        let word_start = self.pos;
+        let word = if operator_len > 0 { self.segments(true)? } else { Vec::new() };
+        let word_text = self.template[word_start..self.pos].to_string();
+
+        match self.rest().chars().next() {
+            Some('}') => self.pos += 1,
+            None => {
+                return Err(RenderError {
+                    message: format!("Unclosed ${{{} expression", name),
+                    span: start..self.template.len(),
+                    name: None,
+                    hint: Some("add the closing }".to_string()),
+                });
+            }
+            Some(c) => {
+                return Err(RenderError {
+                    message: format!("Unsupported '{}' after ${{{}", c, name),
+                    span: start..self.pos + c.len_utf8(),
+                    name: None,
+                    hint: Some("supported forms are ${VAR}, ${VAR:-default}, ${VAR:=default}, ${VAR:?message} \
+                                and ${VAR:+alternative}, with or without the :, and |filters after VAR".to_string()),
+                });
+            }
+        }
 
-pub struct TemplateRenderer {
-    var_regex: Regex,
+        Ok(Segment::Expr {
+            var: VarRef { name: name.to_string(), filters, expansion, word: word_text },
+            word,
+            span: start..self.pos,
+        })
+    }
 }
 
 impl TemplateRenderer {
     pub fn new() -> Self {
-        // Matches ${VAR}, ${VAR:-default}, ${VAR:-}
-        // Capture groups: 1=VAR, 2=:-default (optional), 3=default value (optional)
-        let var_regex = Regex::new(r"\$\{([a-zA-Z_][a-zA-Z0-9_]*)(:-([^}]*))?\}").unwrap();
-
-        TemplateRenderer { var_regex }
+        TemplateRenderer
     }
 
-    /// Renders a template string by replacing ${VAR} and ${VAR:-default} with context values
+    /// Renders a template string by expanding `${VAR}`, `${VAR:-default}` and the other forms
     ///
     /// # Arguments
     /// * `template` - The template string (e.g., "postgresql://${USER}:${PASS}@${HOST:-localhost}")
@@ -24,56 +272,170 @@ impl TemplateRenderer {
     ///
     /// # Returns
     /// * Ok(rendered_string) - Successfully rendered template
-    /// * Err - If a required variable is missing (strict mode: ${VAR} without default)
+    /// * Err - If a required variable is missing (strict mode: ${VAR} without default), a
+    ///   `${VAR:?message}` variable is unset, or an expression is malformed
     pub fn render(&self, template: &str, context: &HashMap<String, String>) -> Result<String> {
-        let mut last_index = 0;
-        let mut rendered = String::new();
-
-        for captures in self.var_regex.captures_iter(template) {
-            let full_match = captures.get(0).unwrap();
-            let var_name = captures.get(1).unwrap().as_str();
-            let has_default = captures.get(2).is_some();
-            let default_value = captures.get(3).map(|m| m.as_str()).unwrap_or("");
-
-            // Add the text before this match
-            rendered.push_str(&template[last_index..full_match.start()]);
-
-            // Resolve the variable
-            // Priority: 1. Context (from .env.example) -> 2. Shell environment -> 3. Default value
-            let resolved_value = if let Some(value) = context.get(var_name) {
-                debug!("Resolved ${{{}}}: '{}' (from context)", var_name, value);
-                value.clone()
-            } else if let Ok(env_value) = std::env::var(var_name) {
-                debug!("Resolved ${{{}}}: '{}' (from shell environment)", var_name, env_value);
-                env_value
-            } else if has_default {
-                debug!("Resolved ${{{}}}: '{}' (using default)", var_name, default_value);
-                default_value.to_string()
-            } else {
-                // Strict mode: variable not found and no default
-                return Err(anyhow!(
-                    "Required variable '{}' not found in context, shell environment, or default. \
-                     Use ${{{}:-default}} syntax to provide a default value.",
-                    var_name, var_name
-                ));
-            };
-
-            rendered.push_str(&resolved_value);
-            last_index = full_match.end();
-        }
+        Ok(self.render_traced(template, context).map(|(rendered, _)| rendered)?)
+    }
 
-        // Add remaining text after last match
-        rendered.push_str(&template[last_index..]);
+    /// Same as `render`, but also returns how each reference was resolved, in template order
+    ///
+    /// Only the words that are actually used are expanded, so a nested reference in an unused
+    /// default is neither resolved nor returned. Variables assigned with `${VAR:=word}` are
+    /// returned as references with source `Default`; later references in the same template
+    /// see the assigned value.
+    pub fn render_traced(
+        &self,
+        template: &str,
+        context: &HashMap<String, String>,
+    ) -> Result<(String, Vec<ResolvedRef>), RenderError> {
+        let segments = parse(template)?;
+        let mut resolved = Vec::new();
+        let mut assigned = HashMap::new();
+        let rendered = render_segments(&segments, context, &mut assigned, &mut resolved)?;
+        Ok((rendered, resolved))
+    }
 
-        Ok(rendered)
+    /// Lists the variable references in a template string, nested ones included, in order of
+    /// appearance; a malformed template has none
+    pub fn references(&self, template: &str) -> Vec<VarRef> {
+        fn collect(segments: &[Segment], refs: &mut Vec<VarRef>) {
+            for segment in segments {
+                if let Segment::Expr { var, word, .. } = segment {
+                    refs.push(var.clone());
+                    collect(word, refs);
+                }
+            }
+        }
+
+        let mut refs = Vec::new();
+        collect(&parse(template).unwrap_or_default(), &mut refs);
+        refs
     }
 
-    /// Checks if a string contains template variables
+    /// Checks if a string contains template expressions, including malformed ones
+    ///
+    /// Escaped `$${` and `\${` are literal text, so a value with only escapes has no variables.
     pub fn contains_variables(&self, s: &str) -> bool {
-        self.var_regex.is_match(s)
+        if !s.contains("${") {
+            return false;
+        }
+        match parse(s) {
+            Ok(segments) => segments.iter().any(|segment| matches!(segment, Segment::Expr { .. })),
+            // Malformed expressions are reported when the value is rendered
+            Err(_) => true,
+        }
+    }
+
+    /// Replaces the `$${` and `\${` escapes in a string without variables with a literal `${`
+    ///
+    /// Strings with variables or malformed expressions are returned unchanged.
+    pub fn unescape(&self, s: &str) -> String {
+        match parse(s) {
+            Ok(segments) if !segments.iter().any(|segment| matches!(segment, Segment::Expr { .. })) => segments.iter()
+                .map(|segment| match segment {
+                    Segment::Text(text) => text.as_str(),
+                    Segment::Expr { .. } => "",
+                })
+                .collect(),
+            _ => s.to_string(),
+        }
     }
 }
 
+fn parse(template: &str) -> Result<Vec<Segment>, RenderError> {
+    Parser { template, pos: 0 }.segments(false)
+}
+
+fn render_segments(
+    segments: &[Segment],
+    context: &HashMap<String, String>,
+    assigned: &mut HashMap<String, String>,
+    resolved: &mut Vec<ResolvedRef>,
+) -> Result<String, RenderError> {
+    let mut rendered = String::new();
+
+    for segment in segments {
+        let (var, word, span) = match segment {
+            Segment::Text(text) => {
+                rendered.push_str(text);
+                continue;
+            }
+            Segment::Expr { var, word, span } => (var, word, span),
+        };
+
+        // Priority: 1. Context (from .env.example) -> 2. Shell environment -> 3. Default value,
+        // except that a `${VAR:=word}` earlier in the template replaces the variable like in bash
+        let current = if let Some(value) = assigned.get(&var.name) {
+            Some((value.clone(), RefSource::Default))
+        } else if let Some(value) = context.get(&var.name) {
+            Some((value.clone(), RefSource::Context))
+        } else {
+            std::env::var(&var.name).ok().map(|value| (value, RefSource::Environment))
+        };
+        let is_set = current.as_ref().is_some_and(|(value, _)| !(var.expansion.colon() && value.is_empty()));
+
+        // References in the word are listed after the expression that uses them
+        let mut nested = Vec::new();
+        let mut expand_word = |assigned: &mut HashMap<String, String>| {
+            render_segments(word, context, assigned, &mut nested)
+        };
+
+        let (value, source) = match (var.expansion, current) {
+            (Expansion::Alternative { .. }, _) if is_set => (expand_word(assigned)?, RefSource::Alternative),
+            (Expansion::Alternative { .. }, _) => (String::new(), RefSource::Default),
+            (_, Some(current)) if is_set => current,
+            (Expansion::Default { .. }, _) => (expand_word(assigned)?, RefSource::Default),
+            (Expansion::Assign { .. }, _) => {
+                let value = expand_word(assigned)?;
+                assigned.insert(var.name.clone(), value.clone());
+                (value, RefSource::Default)
+            }
+            (Expansion::Error { colon }, _) => {
+                let message = match expand_word(assigned)?.as_str() {
+                    "" if colon => "parameter null or not set".to_string(),
+                    "" => "parameter not set".to_string(),
+                    message => message.to_string(),
+                };
+                return Err(RenderError {
+                    message: format!("{}: {}", var.name, message),
+                    span: span.clone(),
+                    name: Some(var.name.clone()),
+                    hint: None,
+                });
+            }
+            (Expansion::Plain, _) => {
+                // Strict mode: variable not found and no default
+                return Err(RenderError {
+                    message: format!(
+                        "Required variable '{}' not found in context, shell environment, or default",
+                        var.name
+                    ),
+                    span: span.clone(),
+                    name: Some(var.name.clone()),
+                    hint: Some(format!("use ${{{}:-default}} to provide a default value", var.name)),
+                });
+            }
+        };
+        debug!("Resolved {}: '{}' ({:?})", var.expression(), value, source);
+
+        let mut filtered = value.clone();
+        for filter in &var.filters {
+            filtered = filter.apply(&filtered).map_err(|e| RenderError {
+                message: format!("Filter {} failed on {}: {}", filter.name(), var.name, e),
+                span: span.clone(),
+                name: None,
+                hint: None,
+            })?;
+        }
+        rendered.push_str(&filtered);
+        resolved.push(ResolvedRef { var: var.clone(), source, value });
+        resolved.append(&mut nested);
+    }
+
+    Ok(rendered)
+}
+
 impl Default for TemplateRenderer {
     fn default() -> Self {
         Self::new()
@@ -137,6 +499,177 @@ mod tests {
         assert_eq!(result, "prefix");
     }
 
+    #[test]
+    fn test_colon_forms_treat_empty_as_unset() {
+        let renderer = TemplateRenderer::new();
+        let mut context = HashMap::new();
+        context.insert("EMPTY".to_string(), String::new());
+        context.insert("HOST".to_string(), "db".to_string());
+
+        assert_eq!(renderer.render("${EMPTY-d}|${EMPTY:-d}|${PSENV_TEST_UNSET-d}", &context).unwrap(), "|d|d");
+        assert_eq!(renderer.render("${EMPTY+a}|${EMPTY:+a}|${HOST:+a}|${PSENV_TEST_UNSET+a}", &context).unwrap(), "a||a|");
+        assert_eq!(renderer.render("${EMPTY?unset}${HOST:?unset}", &context).unwrap(), "db");
+    }
+
+    #[test]
+    fn test_assign_default() {
+        let renderer = TemplateRenderer::new();
+        let mut context = HashMap::new();
+        context.insert("EMPTY".to_string(), String::new());
+
+        // The assigned value is seen by later references in the same string
+        let (rendered, resolved) = renderer
+            .render_traced("${PSENV_TEST_UNSET_PORT:=5432}/${PSENV_TEST_UNSET_PORT}/${EMPTY=x}${EMPTY:=y}", &context)
+            .unwrap();
+        assert_eq!(rendered, "5432/5432/y");
+        assert_eq!(resolved[1].source, RefSource::Default);
+        assert_eq!(resolved[0].var.default(), Some("5432"));
+    }
+
+    #[test]
+    fn test_assign_replaces_empty_context_value() {
+        let renderer = TemplateRenderer::new();
+        let mut context = HashMap::new();
+        context.insert("A".to_string(), String::new());
+
+        // Same as bash: A= ; echo "${A:=x}-${A}" prints x-x
+        let (rendered, resolved) = renderer.render_traced("${A:=x}-${A}", &context).unwrap();
+        assert_eq!(rendered, "x-x");
+        assert_eq!(resolved[1].source, RefSource::Default);
+    }
+
+    #[test]
+    fn test_error_if_unset() {
+        let renderer = TemplateRenderer::new();
+        let mut context = HashMap::new();
+        context.insert("EMPTY".to_string(), String::new());
+
+        let err = renderer.render_traced("x${PSENV_TEST_UNSET_KEY:?set it in SSM}", &context).unwrap_err();
+        assert_eq!(err.message, "PSENV_TEST_UNSET_KEY: set it in SSM");
+        assert_eq!(err.span, 1..39);
+
+        let err = renderer.render("${EMPTY:?}", &context).unwrap_err();
+        assert_eq!(err.to_string(), "EMPTY: parameter null or not set");
+        let err = renderer.render("${PSENV_TEST_UNSET_KEY?}", &context).unwrap_err();
+        assert_eq!(err.to_string(), "PSENV_TEST_UNSET_KEY: parameter not set");
+        assert_eq!(renderer.render("${EMPTY?}", &context).unwrap(), "");
+    }
+
+    #[test]
+    fn test_nested_expressions() {
+        let renderer = TemplateRenderer::new();
+        let mut context = HashMap::new();
+        context.insert("CTX_PG_HOST".to_string(), "db.internal".to_string());
+        context.insert("PG_PORT".to_string(), "5432".to_string());
+
+        let template = "${PSENV_TEST_UNSET_HOST:-${CTX_PG_HOST:-localhost}}";
+        let (rendered, resolved) = renderer.render_traced(template, &context).unwrap();
+        assert_eq!(rendered, "db.internal");
+        assert_eq!(resolved[0].var.word, "${CTX_PG_HOST:-localhost}");
+        assert_eq!(resolved[1].var.name, "CTX_PG_HOST");
+        assert_eq!(resolved[1].source, RefSource::Context);
+
+        assert_eq!(
+            renderer.render("${PSENV_TEST_UNSET_A:-${PSENV_TEST_UNSET_B:-${PG_PORT}}}x", &context).unwrap(),
+            "5432x"
+        );
+        assert_eq!(renderer.render("${PG_PORT:+:${PG_PORT}}", &context).unwrap(), ":5432");
+        // Braces in a word are kept when balanced, as in bash
+        assert_eq!(renderer.render("${PSENV_TEST_UNSET:-{a}}", &context).unwrap(), "{a}");
+        assert_eq!(renderer.render("${PSENV_TEST_UNSET:-a}b}", &context).unwrap(), "ab}");
+        // An unused default is not expanded, so its references don't need to exist
+        assert_eq!(renderer.render("${PG_PORT:-${PSENV_TEST_UNSET}}", &context).unwrap(), "5432");
+    }
+
+    #[test]
+    fn test_escaped_expressions() {
+        let renderer = TemplateRenderer::new();
+        let mut context = HashMap::new();
+        context.insert("DOMAIN".to_string(), "example.com".to_string());
+
+        let (rendered, resolved) = renderer
+            .render_traced("{$${http.request.host}} \\${HOME} ${DOMAIN}", &context)
+            .unwrap();
+        assert_eq!(rendered, "{${http.request.host}} ${HOME} example.com");
+        assert_eq!(resolved.len(), 1);
+
+        // `$$` and `\$` without a brace are kept as they are
+        assert_eq!(renderer.render("pa$$word\\$x", &context).unwrap(), "pa$$word\\$x");
+        assert_eq!(renderer.render("${PSENV_TEST_UNSET:-$${DOMAIN}}", &context).unwrap(), "${DOMAIN}");
+        assert!(renderer.references("$${DOMAIN}").is_empty());
+    }
+
+    #[test]
+    fn test_filters() {
+        let renderer = TemplateRenderer::new();
+        let mut context = HashMap::new();
+        context.insert("PG_USER".to_string(), "app".to_string());
+        context.insert("PG_PASSWORD".to_string(), "p@ss/w:rd".to_string());
+        context.insert("TOKEN".to_string(), " Secret \n".to_string());
+
+        let (rendered, resolved) = renderer
+            .render_traced("postgresql://${PG_USER}:${PG_PASSWORD|urlencode}@db/app", &context)
+            .unwrap();
+        assert_eq!(rendered, "postgresql://app:p%40ss%2Fw%3Ard@db/app");
+        assert_eq!(resolved[1].value, "p@ss/w:rd");
+        assert_eq!(resolved[1].var.expression(), "${PG_PASSWORD|urlencode}");
+
+        // Chained left to right, and applied to defaults and alternatives too
+        assert_eq!(renderer.render("${TOKEN|trim|lower|base64}", &context).unwrap(), "c2VjcmV0");
+        assert_eq!(renderer.render("${TOKEN|trim|base64|base64decode}", &context).unwrap(), "Secret");
+        assert_eq!(renderer.render("${PSENV_TEST_UNSET|upper:-dev}", &context).unwrap(), "DEV");
+        assert_eq!(renderer.render("${PG_USER|json:+${PG_USER}}", &context).unwrap(), "\"app\"");
+    }
+
+    #[test]
+    fn test_filter_errors() {
+        let renderer = TemplateRenderer::new();
+        let mut context = HashMap::new();
+        context.insert("PG_PASSWORD".to_string(), "secret!".to_string());
+
+        let err = renderer.render_traced("x${PG_PASSWORD|urlencod}", &context).unwrap_err();
+        assert_eq!(err.message, "Unknown filter 'urlencod'");
+        assert_eq!(err.span, 14..23);
+        assert_eq!(err.hint.as_deref(), Some("did you mean urlencode?"));
+
+        let err = renderer.render_traced("${PG_PASSWORD|md5}", &context).unwrap_err();
+        assert_eq!(
+            err.hint.as_deref(),
+            Some("available filters are urlencode, base64, base64decode, lower, upper, trim, sha256, json")
+        );
+
+        let err = renderer.render_traced("${PG_PASSWORD|}", &context).unwrap_err();
+        assert_eq!(err.message, "Missing filter name after ${PG_PASSWORD|");
+
+        let err = renderer.render_traced("${PG_PASSWORD|base64decode}", &context).unwrap_err();
+        assert!(err.message.starts_with("Filter base64decode failed on PG_PASSWORD: not valid base64"));
+        assert_eq!(err.span, 0..27);
+    }
+
+    #[test]
+    fn test_malformed_expressions() {
+        let renderer = TemplateRenderer::new();
+        let context = HashMap::new();
+
+        let err = renderer.render_traced("x${PG_HOST:-${CTX_PG_HOST:-localhost}", &context).unwrap_err();
+        assert_eq!(err.message, "Unclosed ${PG_HOST expression");
+        assert_eq!(err.span, 1..37);
+
+        let err = renderer.render_traced("${PG_HOST", &context).unwrap_err();
+        assert_eq!(err.message, "Unclosed ${PG_HOST expression");
+
+        let err = renderer.render_traced("${PG_HOST#db}", &context).unwrap_err();
+        assert_eq!(err.message, "Unsupported '#' after ${PG_HOST");
+        assert_eq!(err.span, 0..10);
+
+        let err = renderer.render_traced("${1PASS}", &context).unwrap_err();
+        assert_eq!(err.message, "Invalid variable name in ${...} expression");
+        assert!(renderer.render_traced("${}", &context).is_err());
+
+        assert!(renderer.contains_variables("${PG_HOST"));
+        assert!(renderer.references("${A:-${B}").is_empty());
+    }
+
     #[test]
     fn test_strict_mode_fails() {
         let renderer = TemplateRenderer::new();
@@ -149,6 +682,17 @@ mod tests {
         assert!(result.unwrap_err().to_string().contains("MISSING_VAR"));
     }
 
+    #[test]
+    fn test_render_error_span() {
+        let renderer = TemplateRenderer::new();
+        let mut context = HashMap::new();
+        context.insert("PG_HOST".to_string(), "localhost".to_string());
+
+        let err = renderer.render_traced("postgresql://${PG_HOST}/${PSENV_TEST_UNSET_DB}", &context).unwrap_err();
+        assert_eq!(err.span, 24..46);
+        assert_eq!(err.name.as_deref(), Some("PSENV_TEST_UNSET_DB"));
+    }
+
     #[test]
     fn test_complex_template() {
         let renderer = TemplateRenderer::new();
@@ -173,6 +717,66 @@ mod tests {
         assert!(renderer.contains_variables("prefix${VAR}suffix"));
         assert!(!renderer.contains_variables("no variables here"));
         assert!(!renderer.contains_variables(""));
+        assert!(!renderer.contains_variables("{$${http.request.host}} \\${HOME}"));
+        assert!(renderer.contains_variables("$${LITERAL} ${VAR}"));
+    }
+
+    #[test]
+    fn test_unescape() {
+        let renderer = TemplateRenderer::new();
+
+        assert_eq!(renderer.unescape("{$${http.request.host}} \\${HOME}"), "{${http.request.host}} ${HOME}");
+        assert_eq!(renderer.unescape("plain $HOME"), "plain $HOME");
+        assert_eq!(renderer.unescape("$${A} ${B}"), "$${A} ${B}");
+    }
+
+    #[test]
+    fn test_references() {
+        let renderer = TemplateRenderer::new();
+
+        let refs = renderer.references("postgresql://${PG_USER}@${PG_HOST:-localhost}/${PG_DB:-}");
+        let parts: Vec<(&str, Expansion, &str)> = refs.iter()
+            .map(|r| (r.name.as_str(), r.expansion, r.word.as_str()))
+            .collect();
+        assert_eq!(
+            parts,
+            vec![
+                ("PG_USER", Expansion::Plain, ""),
+                ("PG_HOST", Expansion::Default { colon: true }, "localhost"),
+                ("PG_DB", Expansion::Default { colon: true }, ""),
+            ]
+        );
+        assert_eq!(refs[1].default(), Some("localhost"));
+        assert_eq!(refs[1].expression(), "${PG_HOST:-localhost}");
+
+        let refs = renderer.references("${A=x}${B:?no B}${C+y}");
+        assert_eq!(refs[0].expansion, Expansion::Assign { colon: false });
+        assert_eq!(refs[1].expansion, Expansion::Error { colon: true });
+        assert_eq!(refs[1].default(), None);
+        assert_eq!(refs[2].expression(), "${C+y}");
+
+        let refs = renderer.references("${PG_HOST:-${CTX_PG_HOST:-localhost}}:${PG_PORT}");
+        let names: Vec<&str> = refs.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["PG_HOST", "CTX_PG_HOST", "PG_PORT"]);
+        assert_eq!(refs[0].default(), Some("${CTX_PG_HOST:-localhost}"));
+        assert!(renderer.references("no variables here").is_empty());
+    }
+
+    #[test]
+    fn test_render_traced_sources() {
+        let renderer = TemplateRenderer::new();
+        let mut context = HashMap::new();
+        context.insert("PG_USER".to_string(), "app_user".to_string());
+
+        let (rendered, resolved) = renderer
+            .render_traced("${PG_USER}@${PSENV_TEST_UNSET_HOST:-localhost}", &context)
+            .unwrap();
+
+        assert_eq!(rendered, "app_user@localhost");
+        assert_eq!(resolved.len(), 2);
+        assert_eq!(resolved[0].source, RefSource::Context);
+        assert_eq!(resolved[1].source, RefSource::Default);
+        assert_eq!(resolved[1].value, "localhost");
     }
 
     #[test]
@@ -0,0 +1,163 @@
+use log::debug;
+use std::collections::HashMap;
+
+use crate::secret_masker::SecretMasker;
+
+/// Builds template values from a set of existing key/value pairs
+///
+/// Sensitive keys get empty values, other keys keep their current value as a
+/// literal default, and values that embed other keys' values are rewritten
+/// into `${...}` expressions.
+pub struct TemplateScaffolder {
+    masker: SecretMasker,
+    min_embed_len: usize,
+}
+
+impl TemplateScaffolder {
+    pub fn new() -> Self {
+        TemplateScaffolder {
+            masker: SecretMasker::new(),
+            // Shorter values ("1", "true", "dev") match too much to be meaningful
+            min_embed_len: 4,
+        }
+    }
+
+    pub fn scaffold(&self, values: &HashMap<String, String>) -> HashMap<String, String> {
+        values
+            .iter()
+            .map(|(key, value)| (key.clone(), self.template_value(key, value, values)))
+            .collect()
+    }
+
+    fn template_value(&self, key: &str, value: &str, values: &HashMap<String, String>) -> String {
+        if self.masker.is_sensitive_key(key) {
+            debug!("{}: sensitive, leaving empty", key);
+            return String::new();
+        }
+
+        // Only strictly shorter values can be embedded, which rules out cycles
+        let mut candidates: Vec<(&String, &String)> = values
+            .iter()
+            .filter(|(other, other_value)| {
+                other.as_str() != key
+                    && other_value.len() >= self.min_embed_len
+                    && other_value.len() < value.len()
+                    && value.contains(other_value.as_str())
+            })
+            .collect();
+        // Longest match first, then by key for a stable result
+        candidates.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then(a.0.cmp(b.0)));
+
+        if candidates.is_empty() {
+            return value.replace("${", "$${");
+        }
+
+        let mut result = String::new();
+        let mut pos = 0;
+        'scan: while pos < value.len() {
+            for (other, other_value) in &candidates {
+                let end = pos + other_value.len();
+                if value[pos..].starts_with(other_value.as_str()) && self.at_boundary(value, pos, end) {
+                    debug!("{}: embeds value of {}", key, other);
+                    result.push_str(&format!("${{{}}}", other));
+                    pos = end;
+                    continue 'scan;
+                }
+            }
+            // A literal `${` in the value must not turn into an expression
+            if value[pos..].starts_with("${") {
+                result.push_str("$${");
+                pos += 2;
+                continue;
+            }
+            let ch = value[pos..].chars().next().unwrap();
+            result.push(ch);
+            pos += ch.len_utf8();
+        }
+
+        result
+    }
+
+    /// A match must not start or end in the middle of a word
+    fn at_boundary(&self, value: &str, start: usize, end: usize) -> bool {
+        let before = value[..start].chars().next_back();
+        let after = value[end..].chars().next();
+        !before.is_some_and(|c| c.is_alphanumeric()) && !after.is_some_and(|c| c.is_alphanumeric())
+    }
+}
+
+impl Default for TemplateScaffolder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_sensitive_keys_are_emptied() {
+        let scaffolder = TemplateScaffolder::new();
+        let result = scaffolder.scaffold(&values(&[
+            ("POSTGRES_PASSWORD", "hunter2hunter2"),
+            ("NODE_ENV", "production"),
+        ]));
+
+        assert_eq!(result["POSTGRES_PASSWORD"], "");
+        assert_eq!(result["NODE_ENV"], "production");
+    }
+
+    #[test]
+    fn test_embedded_values_become_expressions() {
+        let scaffolder = TemplateScaffolder::new();
+        let result = scaffolder.scaffold(&values(&[
+            ("PG_HOST", "postgres"),
+            ("PG_PORT", "5432"),
+            ("PG_USER", "app_user"),
+            ("PG_PASSWORD", "s3cr3t/pass"),
+            ("DATABASE_URL", "postgresql://app_user:s3cr3t/pass@postgres:5432/app"),
+            ("APP_URL", "https://app.example.com"),
+            ("CALLBACK_URL", "https://app.example.com/auth/callback"),
+        ]));
+
+        assert_eq!(
+            result["DATABASE_URL"],
+            "postgresql://${PG_USER}:${PG_PASSWORD}@${PG_HOST}:${PG_PORT}/app"
+        );
+        assert_eq!(result["CALLBACK_URL"], "${APP_URL}/auth/callback");
+        assert_eq!(result["APP_URL"], "https://app.example.com");
+    }
+
+    #[test]
+    fn test_partial_word_matches_are_ignored() {
+        let scaffolder = TemplateScaffolder::new();
+        let result = scaffolder.scaffold(&values(&[
+            ("DEBUG", "true"),
+            ("SITE_NAME", "truenorth"),
+            ("PORT", "80"),
+            ("PUBLIC_URL", "http://truenorth:80"),
+        ]));
+
+        assert_eq!(result["SITE_NAME"], "truenorth");
+        // "80" is below the minimum length, "truenorth" matches on word boundaries
+        assert_eq!(result["PUBLIC_URL"], "http://${SITE_NAME}:80");
+    }
+
+    #[test]
+    fn test_literal_expressions_are_escaped() {
+        let scaffolder = TemplateScaffolder::new();
+        let result = scaffolder.scaffold(&values(&[
+            ("SITE_HOST", "example.com"),
+            ("CADDY_UPSTREAM", "{${http.request.host}}"),
+            ("SITE_URL", "https://example.com/${path}"),
+        ]));
+
+        assert_eq!(result["CADDY_UPSTREAM"], "{$${http.request.host}}");
+        assert_eq!(result["SITE_URL"], "https://${SITE_HOST}/$${path}");
+    }
+}
@@ -0,0 +1,224 @@
+use anyhow::{Context, Result};
+use log::{error, info, warn};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::aws_client::AwsClient;
+use crate::env_handler::{EnvHandler, Strategy};
+use crate::key_filter::{self, KeyFilter};
+use crate::resolver::{MissingPolicy, Resolver};
+use crate::schema::Schema;
+use crate::template_parser::TemplateParser;
+
+/// How often the template file is checked for changes
+const FILE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct WatchOptions {
+    pub template: String,
+    pub prefix: String,
+    pub output: String,
+    pub strategy: Strategy,
+    pub poll_interval: Option<Duration>,
+    pub exec: Option<String>,
+    /// Same key selection and missing-key handling as the default command
+    pub key_filter: KeyFilter,
+    pub require_all: bool,
+    pub missing: MissingPolicy,
+    pub parser: TemplateParser,
+    /// Writes with the same key grammar the template is parsed with
+    pub env_handler: EnvHandler,
+}
+
+/// Regenerates the output file whenever the template or the remote values change
+pub struct Watcher<'a> {
+    options: WatchOptions,
+    aws_client: &'a AwsClient,
+    last_values: Option<HashMap<String, String>>,
+    /// The template and the files it includes
+    files: BTreeSet<String>,
+}
+
+impl<'a> Watcher<'a> {
+    pub fn new(options: WatchOptions, aws_client: &'a AwsClient) -> Self {
+        Watcher {
+            files: BTreeSet::from([options.template.clone()]),
+            options,
+            aws_client,
+            last_values: None,
+        }
+    }
+
+    /// Runs until interrupted with Ctrl-C
+    pub async fn run(&mut self) -> Result<()> {
+        info!("Watching {} (Ctrl-C to stop)", self.options.template);
+        if let Some(interval) = self.options.poll_interval {
+            info!("Polling {} every {}s", self.options.prefix, interval.as_secs());
+        }
+
+        let mut last_modified = self.modified_times();
+        let mut last_poll = Instant::now();
+        self.refresh().await;
+        self.track_new_files(&mut last_modified);
+
+        // Created once, so a Ctrl-C during a refresh or the hook is still seen by the next select
+        let ctrl_c = tokio::signal::ctrl_c();
+        tokio::pin!(ctrl_c);
+
+        loop {
+            tokio::select! {
+                _ = &mut ctrl_c => {
+                    info!("Stopped watching");
+                    return Ok(());
+                }
+                _ = tokio::time::sleep(FILE_CHECK_INTERVAL) => {}
+            }
+
+            let modified = self.modified_times();
+            let poll_due = self.options.poll_interval.is_some_and(|interval| last_poll.elapsed() >= interval);
+
+            if modified != last_modified {
+                info!("Template or included file changed, re-resolving");
+                last_modified = modified;
+            } else if poll_due {
+                info!("Polling Parameter Store");
+            } else {
+                continue;
+            }
+
+            last_poll = Instant::now();
+            self.refresh().await;
+            self.track_new_files(&mut last_modified);
+        }
+    }
+
+    fn modified_times(&self) -> BTreeMap<String, Option<SystemTime>> {
+        self.files.iter().map(|file| (file.clone(), modified_time(file))).collect()
+    }
+
+    /// Starts tracking files that were included since the last check, and stops tracking removed ones
+    fn track_new_files(&self, last_modified: &mut BTreeMap<String, Option<SystemTime>>) {
+        for (file, modified) in self.modified_times() {
+            last_modified.entry(file).or_insert(modified);
+        }
+        last_modified.retain(|file, _| self.files.contains(file));
+    }
+
+    /// Resolves the template and rewrites the output if the result differs
+    async fn refresh(&mut self) {
+        let entries = match self.options.parser.parse_template(&self.options.template) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Failed to parse template file: {}: {:#}", self.options.template, e);
+                return;
+            }
+        };
+        self.files = entries.iter()
+            .filter_map(|e| e.file.clone())
+            .chain([self.options.template.clone()])
+            .collect();
+
+        let (entries, filtered_out) = self.options.key_filter.apply(entries);
+        for (key, dependency) in key_filter::broken_references(&entries, &filtered_out) {
+            warn!("{} depends on {}, which is filtered out", key, dependency);
+        }
+
+        let schema = match Schema::from_entries(&entries) {
+            Ok(schema) => schema,
+            Err(e) => {
+                error!("{:#}; keeping previous output", e);
+                return;
+            }
+        };
+
+        let mut resolution = Resolver::new()
+            .resolve(&entries, self.aws_client, &self.options.prefix)
+            .await;
+
+        if let Err(e) = resolution.enforce(&entries, self.options.require_all, self.options.missing) {
+            warn!("{}; keeping previous output", e);
+            return;
+        }
+
+        // Values aren't logged, they may be secrets
+        let violations = schema.validate(&resolution.values_to_validate());
+        if !violations.is_empty() {
+            let lines: Vec<String> = violations.iter()
+                .map(|violation| format!("  {}: {}", violation.key, violation.message))
+                .collect();
+            warn!("Validation failed; keeping previous output:\n{}", lines.join("\n"));
+            return;
+        }
+
+        let changed = changed_keys(self.last_values.as_ref().unwrap_or(&HashMap::new()), &resolution.context);
+        if changed.is_empty() {
+            info!("No changes");
+            return;
+        }
+        info!("Changed: {}", changed.join(", "));
+
+        if let Err(e) = self.options.env_handler.handle_env_file(
+            &self.options.output,
+            &resolution.context,
+            self.options.strategy.clone(),
+        ) {
+            error!("Failed to handle .env file: {}: {:#}", self.options.output, e);
+            return;
+        }
+        self.last_values = Some(resolution.context);
+
+        if let Err(e) = self.run_hook().await {
+            error!("Hook failed: {:#}", e);
+        }
+    }
+
+    async fn run_hook(&self) -> Result<()> {
+        let Some(command) = &self.options.exec else {
+            return Ok(());
+        };
+
+        info!("Running hook: {}", command);
+        let status = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .status()
+            .await
+            .with_context(|| format!("Failed to run hook: {}", command))?;
+
+        if !status.success() {
+            warn!("Hook exited with {}", status);
+        }
+        Ok(())
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Keys that were added, removed or whose value changed, sorted
+pub fn changed_keys(old: &HashMap<String, String>, new: &HashMap<String, String>) -> Vec<String> {
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_changed_keys() {
+        let old = values(&[("A", "1"), ("B", "2"), ("C", "3")]);
+        let new = values(&[("A", "1"), ("B", "changed"), ("D", "4")]);
+
+        assert_eq!(changed_keys(&old, &new), vec!["B", "C", "D"]);
+        assert!(changed_keys(&old, &old).is_empty());
+    }
+}